@@ -0,0 +1,227 @@
+// Copyright 2018 Leonardo Schwarz <mail@leoschwarz.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! XPath evaluation context.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+use sxd_xpath::context::Evaluation;
+use sxd_xpath::function::{self, Function};
+use sxd_xpath::{self, OwnedQName, Value};
+
+/// The context in which XPath expressions are evaluated.
+///
+/// It contains the functions, variables and namespaces which can be used
+/// in XPath expressions. In contrast to `sxd_xpath::Context` it can be
+/// cloned, so a base context can be configured once and then be extended
+/// for specific queries without modifying the original one.
+///
+/// It dereferences to the wrapped `sxd_xpath::Context`, so it can be passed
+/// to `sxd_xpath` APIs like `XPath::evaluate`. Registrations have to be
+/// made through this type though, so they are retained when cloning.
+///
+/// # Examples
+/// ```
+/// use xpath_reader::{Context, Reader};
+///
+/// let xml = r#"<?xml version="1.0"?><book xmlns="books"><title>Neuromancer</title></book>"#;
+///
+/// let mut context = Context::new();
+/// context.set_namespace("b", "books");
+/// context.set_variable("pos", 1.0);
+///
+/// let reader = Reader::from_str(xml, Some(&context)).unwrap();
+/// let title: String = reader.read("//b:title[$pos]").unwrap();
+/// assert_eq!(title, "Neuromancer");
+/// ```
+pub struct Context<'d> {
+    core_functions: bool,
+    functions: HashMap<OwnedQName, Rc<dyn Function>>,
+    variables: HashMap<OwnedQName, Value<'d>>,
+    namespaces: HashMap<String, String>,
+    inner: sxd_xpath::Context<'d>,
+}
+
+impl<'d> Context<'d> {
+    /// Create a new context with the core XPath 1.0 functions registered.
+    pub fn new() -> Self {
+        Self::with_core_functions(true)
+    }
+
+    /// Create a new context without any functions, variables or namespaces.
+    pub fn without_core_functions() -> Self {
+        Self::with_core_functions(false)
+    }
+
+    fn with_core_functions(core_functions: bool) -> Self {
+        Context {
+            core_functions,
+            functions: HashMap::new(),
+            variables: HashMap::new(),
+            namespaces: HashMap::new(),
+            inner: if core_functions {
+                sxd_xpath::Context::new()
+            } else {
+                sxd_xpath::Context::without_core_functions()
+            },
+        }
+    }
+
+    /// Register a function within the context.
+    pub fn set_function<N, F>(&mut self, name: N, function: F)
+    where
+        N: Into<OwnedQName>,
+        F: Function + 'static,
+    {
+        let name = name.into();
+        let function: Rc<dyn Function> = Rc::new(function);
+        self.inner
+            .set_function(name.clone(), SharedFunction(function.clone()));
+        self.functions.insert(name, function);
+    }
+
+    /// Register a variable within the context.
+    pub fn set_variable<N, V>(&mut self, name: N, value: V)
+    where
+        N: Into<OwnedQName>,
+        V: Into<Value<'d>>,
+    {
+        let name = name.into();
+        let value = value.into();
+        self.inner.set_variable(name.clone(), value.clone());
+        self.variables.insert(name, value);
+    }
+
+    /// Register a namespace prefix within the context.
+    pub fn set_namespace(&mut self, prefix: &str, uri: &str) {
+        self.inner.set_namespace(prefix, uri);
+        self.namespaces.insert(prefix.to_string(), uri.to_string());
+    }
+
+    pub(crate) fn inner(&self) -> &sxd_xpath::Context<'d> {
+        &self.inner
+    }
+}
+
+impl<'d> Default for Context<'d> {
+    fn default() -> Self {
+        Context::new()
+    }
+}
+
+impl<'d> Deref for Context<'d> {
+    type Target = sxd_xpath::Context<'d>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'d> fmt::Debug for Context<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("Context")
+            .field("core_functions", &self.core_functions)
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("variables", &self.variables)
+            .field("namespaces", &self.namespaces)
+            .finish()
+    }
+}
+
+impl<'d> Clone for Context<'d> {
+    fn clone(&self) -> Self {
+        let mut context = Self::with_core_functions(self.core_functions);
+        for (name, function) in &self.functions {
+            context
+                .inner
+                .set_function(name.clone(), SharedFunction(function.clone()));
+        }
+        for (name, value) in &self.variables {
+            context.inner.set_variable(name.clone(), value.clone());
+        }
+        for (prefix, uri) in &self.namespaces {
+            context.inner.set_namespace(prefix, uri);
+        }
+        context.functions = self.functions.clone();
+        context.variables = self.variables.clone();
+        context.namespaces = self.namespaces.clone();
+        context
+    }
+}
+
+/// Function registered in multiple sxd contexts at once.
+struct SharedFunction(Rc<dyn Function>);
+
+impl Function for SharedFunction {
+    fn evaluate<'c, 'd>(
+        &self,
+        context: &Evaluation<'c, 'd>,
+        args: Vec<Value<'d>>,
+    ) -> Result<Value<'d>, function::Error> {
+        self.0.evaluate(context, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Reader;
+
+    #[test]
+    fn clone_independent() {
+        let xml = r#"<?xml version="1.0"?><root xmlns="urn:r"><a>1</a><a>2</a></root>"#;
+
+        let mut base = Context::new();
+        base.set_namespace("r", "urn:r");
+        let mut derived = base.clone();
+        derived.set_variable("pos", 2.0);
+
+        let reader = Reader::from_str(xml, Some(&base)).unwrap();
+        assert_eq!(reader.read::<u32, _>("//r:a").unwrap(), 1);
+        assert!(reader.read::<u32, _>("//r:a[$pos]").is_err());
+
+        let reader = Reader::from_str(xml, Some(&derived)).unwrap();
+        assert_eq!(reader.read::<u32, _>("//r:a[$pos]").unwrap(), 2);
+    }
+
+    #[test]
+    fn deref() {
+        let xml = r#"<?xml version="1.0"?><root><a>1</a><a>2</a></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let mut context = Context::new();
+        context.set_variable("pos", 2.0);
+        let xpath = sxd_xpath::Factory::new()
+            .build("//a[$pos]")
+            .unwrap()
+            .unwrap();
+        let value = xpath.evaluate(&context, reader.anchor_node().unwrap());
+        assert_eq!(value.unwrap().string(), "2");
+    }
+
+    #[test]
+    fn debug() {
+        let mut context = Context::without_core_functions();
+        context.set_namespace("r", "urn:r");
+        context.set_variable("pos", 2.0);
+
+        let debug = format!("{:?}", context);
+        assert!(debug.contains("core_functions: false"));
+        assert!(debug.contains(r#""r": "urn:r""#));
+        assert!(debug.contains("pos"));
+        assert!(debug.contains("Number(2.0)"));
+    }
+}
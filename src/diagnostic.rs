@@ -0,0 +1,199 @@
+// Copyright 2018 Leonardo Schwarz <mail@leoschwarz.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading with diagnostics about absent and invalid values.
+
+use errors::Error;
+use expression::XPathExpression;
+use reader::{FromXml, Reader};
+
+/// Wraps a `Reader` and records the expressions of optional reads which
+/// selected no node, as well as the errors of lenient reads.
+///
+/// This is useful for lenient parsers, which accept documents lacking
+/// optional fields but want to report which fields were absent, and for
+/// tooling which wants a best-effort result for a whole document together
+/// with a report of every field which could not be read.
+///
+/// # Examples
+/// ```
+/// use xpath_reader::{DiagnosticReader, Reader};
+///
+/// let xml = r#"<?xml version="1.0"?><book><title>Neuromancer</title><pages>many</pages></book>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let mut diagnostic = DiagnosticReader::new(&reader);
+///
+/// let title: Option<String> = diagnostic.read_option("//title").unwrap();
+/// let isbn: Option<String> = diagnostic.read_option("//isbn").unwrap();
+/// assert_eq!(title, Some("Neuromancer".to_string()));
+/// assert_eq!(isbn, None);
+/// assert_eq!(diagnostic.misses(), &["//isbn".to_string()]);
+///
+/// let pages: u32 = diagnostic.read("//pages");
+/// assert_eq!(pages, 0);
+/// assert_eq!(diagnostic.errors().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct DiagnosticReader<'d> {
+    reader: &'d Reader<'d>,
+    misses: Vec<String>,
+    errors: Vec<Error>,
+}
+
+impl<'d> DiagnosticReader<'d> {
+    /// Creates a new `DiagnosticReader` reading from `reader`.
+    pub fn new(reader: &'d Reader<'d>) -> Self {
+        DiagnosticReader {
+            reader,
+            misses: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// References the wrapped reader, e.g. to read required values.
+    pub fn reader(&self) -> &'d Reader<'d> {
+        self.reader
+    }
+
+    /// Read the result of the XPath expression into an optional value,
+    /// recording the expression if it selected no node.
+    ///
+    /// Errors carry the expression which failed, see `Error::expression`.
+    pub fn read_option<'a, V, X>(&mut self, xpath_expr: X) -> Result<Option<V>, Error>
+    where
+        Option<V>: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath_expr = xpath_expr.into();
+        let nodes = self.reader.with_nodeset_eval(&xpath_expr)?;
+        if nodes.anchor_node().is_none() {
+            self.misses.push(xpath_expr.to_string());
+        }
+        Option::<V>::from_xml(&nodes).map_err(|e| e.with_expression(xpath_expr.to_string()))
+    }
+
+    /// Read the result of the XPath expression, substituting `V::default()`
+    /// and recording the error if the read failed.
+    pub fn read<'a, V, X>(&mut self, xpath_expr: X) -> V
+    where
+        V: FromXml + Default,
+        X: Into<XPathExpression<'a>>,
+    {
+        self.read_or(xpath_expr, V::default())
+    }
+
+    /// Read the result of the XPath expression, substituting `default` and
+    /// recording the error if the read failed.
+    ///
+    /// The recorded errors carry the expression which failed, see
+    /// `Error::expression`.
+    pub fn read_or<'a, V, X>(&mut self, xpath_expr: X, default: V) -> V
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath_expr = xpath_expr.into();
+        match self.reader.read(&xpath_expr) {
+            Ok(value) => value,
+            Err(e) => {
+                self.errors.push(e.with_expression(xpath_expr.to_string()));
+                default
+            }
+        }
+    }
+
+    /// Returns the expressions which selected no node, in the order they
+    /// were read.
+    pub fn misses(&self) -> &[String] {
+        &self.misses
+    }
+
+    /// Consumes the `DiagnosticReader`, returning the expressions which
+    /// selected no node.
+    pub fn into_misses(self) -> Vec<String> {
+        self.misses
+    }
+
+    /// Returns the errors of the failed lenient reads, in the order they
+    /// were read.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Consumes the `DiagnosticReader`, returning the errors of the failed
+    /// lenient reads.
+    pub fn into_errors(self) -> Vec<Error> {
+        self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misses() {
+        let xml = r#"<?xml version="1.0"?>
+                     <book><title>Neuromancer</title><pages>271</pages><isbn/></book>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let mut diagnostic = DiagnosticReader::new(&reader);
+
+        let title: Option<String> = diagnostic.read_option("//title").unwrap();
+        let author: Option<String> = diagnostic.read_option("//author").unwrap();
+        let pages: Option<u32> = diagnostic.read_option("//pages").unwrap();
+        let year: Option<u32> = diagnostic.read_option("//year").unwrap();
+        let isbn: Option<String> = diagnostic.read_option("//isbn").unwrap();
+
+        assert_eq!(title, Some("Neuromancer".to_string()));
+        assert_eq!(author, None);
+        assert_eq!(pages, Some(271));
+        assert_eq!(year, None);
+        assert_eq!(isbn, None);
+        let err = diagnostic.read_option::<u32, _>("//title").err().unwrap();
+        assert_eq!(err.expression(), Some("//title"));
+        assert_eq!(
+            diagnostic.into_misses(),
+            vec!["//author".to_string(), "//year".to_string()]
+        );
+    }
+
+    #[test]
+    fn collects_errors() {
+        let xml = r#"<?xml version="1.0"?>
+                     <book><title>Neuromancer</title><pages>many</pages>
+                     <year>1984</year><price>cheap</price><isbn/></book>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let mut diagnostic = DiagnosticReader::new(&reader);
+
+        let title: String = diagnostic.read("//title");
+        let pages: u32 = diagnostic.read("//pages");
+        let year: u32 = diagnostic.read("//year");
+        let price: f64 = diagnostic.read_or("//price", 9.99);
+        let ratings: Vec<u8> = diagnostic.read("//rating");
+        let edition: u8 = diagnostic.read("//edition");
+
+        assert_eq!(title, "Neuromancer");
+        assert_eq!(pages, 0);
+        assert_eq!(year, 1984);
+        assert_eq!(price, 9.99);
+        assert_eq!(ratings, Vec::<u8>::new());
+        assert_eq!(edition, 0);
+
+        let errors = diagnostic.into_errors();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].expression(), Some("//pages"));
+        assert_eq!(errors[1].expression(), Some("//price"));
+        assert_eq!(errors[2].expression(), Some("//edition"));
+    }
+}
@@ -1,5 +1,8 @@
 //! Error definitions.
 
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::convert::Infallible;
+use std::num::TryFromIntError;
 use std::{error, fmt};
 
 /// The error type used throughout the crate.
@@ -7,10 +10,14 @@ use std::{error, fmt};
 pub struct Error {
     kind: ErrorKind,
     data: ErrorData,
+    expression: Option<Box<str>>,
+    context: Vec<String>,
+    backtrace: Backtrace,
 }
 
 /// Describes the kind of the error.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum ErrorKind {
     /// There was an error parsing the XML document.
     ParseXml,
@@ -18,72 +25,245 @@ pub enum ErrorKind {
     ParseXPath,
     /// There was an error evaluation the XPath expression.
     EvalXPath,
+    /// A value could not be converted from its XML representation.
+    FromXml,
+    /// A required value was missing.
+    MissingValue,
+    /// A configured limit was exceeded.
+    LimitExceeded,
+    /// The maximum nesting depth of readers was exceeded.
+    DepthLimitExceeded,
     /// There was an other error.
     Other,
 }
 
-pub(crate) trait InternalError: fmt::Display + fmt::Debug + Send + Sync {}
+/// A stable, coarse category of an error.
+///
+/// In contrast to `ErrorKind`, which may gain new variants over time, the
+/// categories and their numeric values are meant to stay stable, e.g. for
+/// FFI or structured logging.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[repr(u8)]
+pub enum ErrorCategory {
+    /// Any error not covered by the other categories.
+    Other = 0,
+    /// The XML document could not be parsed.
+    ParseXml = 1,
+    /// The XPath expression could not be parsed.
+    ParseXPath = 2,
+    /// The XPath expression could not be evaluated.
+    EvalXPath = 3,
+    /// A value could not be converted from XML.
+    Conversion = 4,
+    /// A required value was missing.
+    MissingValue = 5,
+    /// A limit was exceeded.
+    LimitExceeded = 6,
+}
 
-impl<T> InternalError for T
-where
-    T: fmt::Display + fmt::Debug + Send + Sync,
-{
+/// A structured view of an `Error`, e.g. for machine-consumable error
+/// reporting.
+///
+/// With the `serde` feature enabled this implements `serde::Serialize`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ErrorReport<'a> {
+    /// The stable category of the error.
+    pub category: ErrorCategory,
+    /// The kind of the error.
+    pub kind: ErrorKind,
+    /// The error message.
+    pub message: String,
+    /// The XPath expression which caused the error, if known.
+    pub expression: Option<&'a str>,
 }
 
+pub(crate) trait InternalError: fmt::Display + fmt::Debug + Send + Sync {}
+
+impl<T> InternalError for T where T: fmt::Display + fmt::Debug + Send + Sync {}
+
 #[derive(Debug)]
 enum ErrorData {
-    Internal(Box<InternalError>),
+    Internal(Box<dyn InternalError>),
     Custom(CustomError),
 }
 
 #[derive(Debug)]
 pub enum CustomError {
     Message(String),
-    Error(Box<error::Error + Send + Sync>),
+    Error(Box<dyn error::Error + Send + Sync>),
 }
 
 impl Error {
     pub(crate) fn internal<E: 'static + InternalError>(error: E, kind: ErrorKind) -> Self {
+        Error::new(kind, ErrorData::Internal(Box::new(error)))
+    }
+
+    fn new(kind: ErrorKind, data: ErrorData) -> Self {
         Error {
-            kind: kind,
-            data: ErrorData::Internal(Box::new(error)),
+            kind,
+            data,
+            expression: None,
+            context: Vec::new(),
+            // Only walks the stack if backtraces are enabled through the
+            // environment, otherwise this is a cheap check of a cached flag.
+            backtrace: Backtrace::capture(),
         }
     }
 
+    /// Records the XPath expression which caused this error, unless one was
+    /// already recorded.
+    pub(crate) fn with_expression<S: Into<String>>(mut self, expression: S) -> Self {
+        if self.expression.is_none() {
+            self.expression = Some(expression.into().into_boxed_str());
+        }
+        self
+    }
+
     /// Returns the error kind of this error.
     pub fn kind(&self) -> ErrorKind {
         self.kind
     }
 
+    /// Returns the stable category of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self.kind {
+            ErrorKind::ParseXml => ErrorCategory::ParseXml,
+            ErrorKind::ParseXPath => ErrorCategory::ParseXPath,
+            ErrorKind::EvalXPath => ErrorCategory::EvalXPath,
+            ErrorKind::FromXml => ErrorCategory::Conversion,
+            ErrorKind::MissingValue => ErrorCategory::MissingValue,
+            ErrorKind::LimitExceeded | ErrorKind::DepthLimitExceeded => {
+                ErrorCategory::LimitExceeded
+            }
+            ErrorKind::Other => ErrorCategory::Other,
+        }
+    }
+
+    /// Returns the XPath expression which caused this error, if known.
+    pub fn expression(&self) -> Option<&str> {
+        self.expression.as_deref()
+    }
+
+    /// Adds a message describing what was being done when this error
+    /// occurred, like `while reading field 'price'`.
+    ///
+    /// Context messages are prepended to the error message, the one added
+    /// last comes first. The kind of the error is retained.
+    pub fn with_context<S: Into<String>>(mut self, context: S) -> Self {
+        self.context.push(context.into());
+        self
+    }
+
+    /// Returns the error message including any context, without the kind
+    /// or source.
+    pub fn message(&self) -> String {
+        let mut message = String::new();
+        for context in self.context.iter().rev() {
+            message.push_str(context);
+            message.push_str(": ");
+        }
+        match self.data {
+            ErrorData::Internal(ref e) => message.push_str(&e.to_string()),
+            ErrorData::Custom(CustomError::Message(ref s)) => message.push_str(s),
+            ErrorData::Custom(CustomError::Error(ref e)) => message.push_str(&e.to_string()),
+        }
+        message
+    }
+
+    /// Returns a structured view of this error.
+    pub fn report(&self) -> ErrorReport<'_> {
+        ErrorReport {
+            category: self.category(),
+            kind: self.kind,
+            message: self.message(),
+            expression: self.expression(),
+        }
+    }
+
     /// Create a new custom error by providing an error message.
     pub fn custom_msg<S: Into<String>>(s: S) -> Self {
         let data = CustomError::Message(s.into());
-        Error {
-            kind: ErrorKind::Other,
-            data: ErrorData::Custom(data),
-        }
+        Error::new(ErrorKind::Other, ErrorData::Custom(data))
+    }
+
+    /// Creates an error of kind `ErrorKind::FromXml`, used by the macros of
+    /// this crate.
+    #[doc(hidden)]
+    pub fn __from_xml_msg<S: Into<String>>(s: S) -> Self {
+        Error::internal(s.into(), ErrorKind::FromXml)
     }
 
     /// Create a new custom error by providing an error object.
     pub fn custom_err<E: 'static + error::Error + Send + Sync>(e: E) -> Self {
         let data = CustomError::Error(Box::new(e));
-        Error {
-            kind: ErrorKind::Other,
-            data: ErrorData::Custom(data),
+        Error::new(ErrorKind::Other, ErrorData::Custom(data))
+    }
+
+    /// Returns the backtrace of the location where this error was created.
+    ///
+    /// Backtraces are only captured if enabled through the `RUST_BACKTRACE`
+    /// or `RUST_LIB_BACKTRACE` environment variables, otherwise `None` is
+    /// returned. Note that enabling them makes creating errors more
+    /// expensive, which is noticeable where errors are expected and
+    /// discarded, e.g. when reading optional values.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self.backtrace.status() {
+            BacktraceStatus::Captured => Some(&self.backtrace),
+            _ => None,
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "xpath_reader error: kind = {:?}, message = ", self.kind)?;
-        match self.data {
-            ErrorData::Internal(ref e) => write!(f, "{}, source = internal", e),
-            ErrorData::Custom(CustomError::Message(ref s)) => {
-                write!(f, "{}, source = custom msg", s)
-            }
-            ErrorData::Custom(CustomError::Error(ref e)) => write!(f, "{}, source = custom_err", e),
-        }
+        let source = match self.data {
+            ErrorData::Internal(_) => "internal",
+            ErrorData::Custom(CustomError::Message(_)) => "custom msg",
+            ErrorData::Custom(CustomError::Error(_)) => "custom_err",
+        };
+        write!(
+            f,
+            "xpath_reader error: kind = {:?}, message = {}, source = {}",
+            self.kind,
+            self.message(),
+            source
+        )
+    }
+}
+
+impl From<TryFromIntError> for Error {
+    fn from(e: TryFromIntError) -> Self {
+        Error::internal(e, ErrorKind::FromXml)
+    }
+}
+
+/// Allows generic code over `FromStr` types with `Err = Infallible`,
+/// like `String`, to convert their error into `Error`.
+///
+/// # Examples
+/// ```
+/// use std::str::FromStr;
+/// use xpath_reader::{Error, Reader};
+///
+/// fn read_parsed<T>(reader: &Reader, xpath: &str) -> Result<T, Error>
+/// where
+///     T: FromStr,
+///     Error: From<T::Err>,
+/// {
+///     let s: String = reader.read(xpath)?;
+///     Ok(s.parse::<T>()?)
+/// }
+///
+/// let xml = r#"<?xml version="1.0"?><root><name>Neuromancer</name></root>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let name: String = read_parsed(&reader, "//name").unwrap();
+/// assert_eq!(name, "Neuromancer");
+/// ```
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Self {
+        match e {}
     }
 }
 
@@ -92,3 +272,88 @@ impl error::Error for Error {
         "xpath_reader error"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backtrace() {
+        let enabled = Backtrace::capture().status() == BacktraceStatus::Captured;
+        let err = Error::custom_msg("failure");
+        assert_eq!(err.backtrace().is_some(), enabled);
+    }
+
+    #[test]
+    fn category() {
+        let category = |kind| Error::internal("failure", kind).category();
+        assert_eq!(category(ErrorKind::ParseXml), ErrorCategory::ParseXml);
+        assert_eq!(category(ErrorKind::ParseXPath), ErrorCategory::ParseXPath);
+        assert_eq!(category(ErrorKind::EvalXPath), ErrorCategory::EvalXPath);
+        assert_eq!(category(ErrorKind::FromXml), ErrorCategory::Conversion);
+        assert_eq!(
+            category(ErrorKind::MissingValue),
+            ErrorCategory::MissingValue
+        );
+        assert_eq!(
+            category(ErrorKind::LimitExceeded),
+            ErrorCategory::LimitExceeded
+        );
+        assert_eq!(
+            category(ErrorKind::DepthLimitExceeded),
+            ErrorCategory::LimitExceeded
+        );
+        assert_eq!(category(ErrorKind::Other), ErrorCategory::Other);
+        assert_eq!(
+            Error::custom_msg("failure").category(),
+            ErrorCategory::Other
+        );
+        assert_eq!(ErrorCategory::MissingValue as u8, 5);
+    }
+
+    #[test]
+    fn report() {
+        let err = Error::internal("failure", ErrorKind::EvalXPath).with_expression("//a");
+        let report = err.report();
+        assert_eq!(report.category, ErrorCategory::EvalXPath);
+        assert_eq!(report.message, "failure");
+        assert_eq!(report.expression, Some("//a"));
+        assert_eq!(Error::custom_msg("failure").report().expression, None);
+    }
+
+    #[test]
+    fn context() {
+        let err = Error::internal("invalid digit", ErrorKind::FromXml)
+            .with_context("while reading field 'price'")
+            .with_context("while reading field 'item'");
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert_eq!(
+            err.message(),
+            "while reading field 'item': while reading field 'price': invalid digit"
+        );
+        assert_eq!(
+            err.to_string(),
+            "xpath_reader error: kind = FromXml, message = while reading field 'item': \
+             while reading field 'price': invalid digit, source = internal"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn report_json() {
+        use reader::Reader;
+
+        let xml = r#"<?xml version="1.0"?><root><item/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let err = reader
+            .read_attr_at::<String, _>("//missing", "id")
+            .err()
+            .unwrap();
+
+        let json = serde_json::to_string(&err.report()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"category":"MissingValue","kind":"MissingValue","message":"Element '//missing' not found.","expression":"//missing"}"#
+        );
+    }
+}
@@ -19,6 +19,7 @@
 
 use errors::{Error, ErrorKind};
 use std::borrow::Borrow;
+use std::fmt;
 use sxd_xpath::{Factory, XPath};
 use util::Refable;
 
@@ -46,20 +47,29 @@ enum Repr<'a> {
 }
 
 impl<'a> XPathExpression<'a> {
-    pub(crate) fn parsed(&self) -> Result<Refable<XPath>, Error> {
+    pub(crate) fn parsed(&self) -> Result<Refable<'_, XPath>, Error> {
         match self.0 {
             Repr::Parsed(ref refable) => Ok(refable.clone_ref()),
-            Repr::Unparsed(ref s) => parse_xpath(s).map(|x| Refable::Owned(x)),
+            Repr::Unparsed(s) => parse_xpath(s).map(Refable::Owned),
         }
     }
 
-    pub(crate) fn to_string(&self) -> String {
+    pub(crate) fn unparsed(&self) -> Option<&'a str> {
+        match self.0 {
+            Repr::Parsed(_) => None,
+            Repr::Unparsed(s) => Some(s),
+        }
+    }
+}
+
+impl<'a> fmt::Display for XPathExpression<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self.0 {
             Repr::Parsed(ref refable) => {
                 let xpath: &XPath = refable.borrow();
-                format!("{:?}", xpath)
+                write!(f, "{:?}", xpath)
             }
-            Repr::Unparsed(ref s) => s.to_string(),
+            Repr::Unparsed(s) => write!(f, "{}", s),
         }
     }
 }
@@ -86,14 +96,78 @@ impl<'a> From<&'a XPathExpression<'a>> for XPathExpression<'a> {
     fn from(x: &'a XPathExpression<'a>) -> Self {
         match x.0 {
             Repr::Parsed(ref refable) => XPathExpression(Repr::Parsed(refable.clone_ref())),
-            Repr::Unparsed(ref s) => XPathExpression(Repr::Unparsed(s)),
+            Repr::Unparsed(s) => XPathExpression(Repr::Unparsed(s)),
         }
     }
 }
 
-fn parse_xpath(xpath_expr: &str) -> Result<XPath, Error> {
+/// Returns an XPath string literal for `s`, which can safely be embedded
+/// into an expression.
+///
+/// XPath 1.0 string literals have no escape mechanism and can't contain
+/// the quote character delimiting them, so strings containing both kinds
+/// of quotes are built with `concat()`.
+///
+/// # Examples
+/// ```
+/// use xpath_reader::expression::escape_xpath_string;
+///
+/// assert_eq!(escape_xpath_string("Neuromancer"), "'Neuromancer'");
+/// assert_eq!(escape_xpath_string("Ender's Game"), r#""Ender's Game""#);
+///
+/// let xpath = format!("//book[@title = {}]", escape_xpath_string("Ender's Game"));
+/// assert_eq!(xpath, r#"//book[@title = "Ender's Game"]"#);
+/// ```
+pub fn escape_xpath_string(s: &str) -> String {
+    if !s.contains('\'') {
+        format!("'{}'", s)
+    } else if !s.contains('"') {
+        format!("\"{}\"", s)
+    } else {
+        let parts: Vec<String> = s.split('\'').map(|part| format!("'{}'", part)).collect();
+        format!("concat({})", parts.join(", \"'\", "))
+    }
+}
+
+pub(crate) fn parse_xpath(xpath_expr: &str) -> Result<XPath, Error> {
     Factory::new()
         .build(xpath_expr)
         .map_err(|e| Error::internal(format!("{}", e), ErrorKind::ParseXPath))?
         .ok_or_else(|| Error::internal("Empty XPath expression.", ErrorKind::ParseXPath))
+        .map_err(|e| e.with_expression(xpath_expr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::Reader;
+
+    #[test]
+    fn escape_string() {
+        assert_eq!(escape_xpath_string(""), "''");
+        assert_eq!(escape_xpath_string("it's"), r#""it's""#);
+        assert_eq!(escape_xpath_string(r#"say "hi""#), r#"'say "hi"'"#);
+        assert_eq!(
+            escape_xpath_string(r#"it's "odd""#),
+            r#"concat('it', "'", 's "odd"')"#
+        );
+        assert_eq!(
+            escape_xpath_string(r#"'"'"#),
+            r#"concat('', "'", '"', "'", '')"#
+        );
+    }
+
+    #[test]
+    fn escape_string_roundtrip() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><s/><s>plain</s><s>it's</s><s>say "hi"</s><s>it's "odd"</s><s>'"'</s></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let strings: Vec<String> = reader.read("//s").unwrap();
+        for s in &strings {
+            let xpath = format!("//s[. = {}]", escape_xpath_string(s));
+            assert_eq!(&reader.read::<String, _>(xpath.as_str()).unwrap(), s);
+        }
+        assert_eq!(strings.len(), 6);
+    }
 }
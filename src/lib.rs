@@ -41,14 +41,31 @@
 
 #![warn(missing_docs)]
 
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "rust_decimal")]
+extern crate rust_decimal;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 extern crate sxd_document;
 extern crate sxd_xpath;
 
+#[macro_use]
+mod macros;
+mod context;
+mod diagnostic;
 mod errors;
-mod util;
 pub mod expression;
 pub mod reader;
-pub use self::errors::{Error, ErrorKind};
-pub use self::reader::{FromXml, FromXmlOptional, FromXmlResult, Reader};
-// TODO: Replace the documentation of Context with an example for xpath_reader.
-pub use sxd_xpath::Context;
+pub mod stream;
+pub mod types;
+mod util;
+pub use self::context::Context;
+pub use self::diagnostic::DiagnosticReader;
+pub use self::errors::{Error, ErrorCategory, ErrorKind, ErrorReport};
+pub use self::reader::{
+    is_well_formed, FromXml, FromXmlOptional, FromXmlResult, Reader, ValidateXml,
+};
@@ -0,0 +1,444 @@
+// Copyright 2018 Leonardo Schwarz <mail@leoschwarz.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Macros to reduce the boilerplate of `FromXml` implementations.
+
+/// Implements `FromXml` for an enum by dispatching on the value of an
+/// attribute of the anchor node.
+///
+/// Each discriminant value is mapped to a tuple variant wrapping a single
+/// `FromXml` type, which is then read from the same reader. An unknown
+/// discriminant results in an error.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate xpath_reader;
+///
+/// use xpath_reader::{FromXml, FromXmlResult, Reader};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Circle(f64);
+///
+/// impl FromXml for Circle {
+///     fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+///         Ok(Circle(reader.read("@r")?))
+///     }
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Shape {
+///     Circle(Circle),
+/// }
+///
+/// from_xml_dispatch!(Shape, "type", {
+///     "circle" => Circle(Circle),
+/// });
+///
+/// # fn main() {
+/// let xml = r#"<?xml version="1.0"?><shape type="circle" r="5"/>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let shape: Shape = reader.read("/shape").unwrap();
+/// assert_eq!(shape, Shape::Circle(Circle(5.0)));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_xml_dispatch {
+    ( $enum:ident, $attr:expr, { $( $disc:expr => $variant:ident($ty:ty) ),* $(,)* } ) => {
+        impl $crate::FromXml for $enum {
+            fn from_xml<'d>(reader: &'d $crate::Reader<'d>) -> $crate::FromXmlResult<Self> {
+                let discriminant: String = reader.read(format!("@{}", $attr).as_str())?;
+                $(
+                    if discriminant == $disc {
+                        let value = <$ty as $crate::FromXml>::from_xml(reader)?;
+                        return Ok($enum::$variant(value));
+                    }
+                )*
+                Err($crate::Error::__from_xml_msg(format!(
+                    "Unknown discriminant '{}' in attribute '{}'.",
+                    discriminant, $attr
+                )))
+            }
+        }
+    };
+}
+
+/// Implements `FromXml` for an enum by dispatching on the namespace URI and
+/// local name of the anchor element.
+///
+/// This is useful for choice groups mixing vocabularies, where elements with
+/// the same local name but different namespaces have different meanings.
+/// Each `(namespace_uri, local_name)` pair is mapped to a tuple variant
+/// wrapping a single `FromXml` type, which is then read from the same
+/// reader. Use `""` as URI for elements without a namespace. Any other
+/// element results in an error.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate xpath_reader;
+///
+/// use xpath_reader::Reader;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Link {
+///     Atom(String),
+///     Html(String),
+/// }
+///
+/// from_xml_element_dispatch!(Link, {
+///     ("http://www.w3.org/2005/Atom", "link") => Atom(String),
+///     ("http://www.w3.org/1999/xhtml", "link") => Html(String),
+/// });
+///
+/// # fn main() {
+/// let xml = r#"<?xml version="1.0"?>
+///     <feed xmlns="http://www.w3.org/2005/Atom"><link>a</link><content>
+///     <div xmlns="http://www.w3.org/1999/xhtml"><link>b</link></div></content></feed>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let links: Vec<Link> = reader.read("//*[local-name() = 'link']").unwrap();
+/// assert_eq!(links, vec![Link::Atom("a".into()), Link::Html("b".into())]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_xml_element_dispatch {
+    ( $enum:ident, { $( ($uri:expr, $name:expr) => $variant:ident($ty:ty) ),* $(,)* } ) => {
+        impl $crate::FromXml for $enum {
+            fn from_xml<'d>(reader: &'d $crate::Reader<'d>) -> $crate::FromXmlResult<Self> {
+                let uri: String = reader.read("namespace-uri()")?;
+                let name: String = reader.read("local-name()")?;
+                $(
+                    if uri == $uri && name == $name {
+                        let value = <$ty as $crate::FromXml>::from_xml(reader)?;
+                        return Ok($enum::$variant(value));
+                    }
+                )*
+                Err($crate::Error::__from_xml_msg(format!(
+                    "Unknown element '{}' in namespace '{}'.",
+                    name, uri
+                )))
+            }
+        }
+    };
+}
+
+/// Implements `FromXml` for a newtype wrapping a single `FromXml` value.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate xpath_reader;
+///
+/// use xpath_reader::Reader;
+///
+/// struct Isbn(String);
+///
+/// from_xml_newtype!(Isbn, String);
+///
+/// # fn main() {
+/// let xml = r#"<?xml version="1.0"?><book isbn="0-441-56959-5"/>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let isbn: Isbn = reader.read("//@isbn").unwrap();
+/// assert_eq!(isbn.0, "0-441-56959-5");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_xml_newtype {
+    ( $type:ident, $inner:ty ) => {
+        impl $crate::FromXml for $type {
+            fn from_xml<'d>(reader: &'d $crate::Reader<'d>) -> $crate::FromXmlResult<Self> {
+                <$inner as $crate::FromXml>::from_xml(reader).map($type)
+            }
+        }
+    };
+}
+
+/// Implements `FromXml` for an extensible enum, mapping known string values
+/// to unit variants and preserving any other value in a fallback variant.
+///
+/// In contrast to rejecting unknown values, this keeps documents using
+/// values added in later versions of a format readable.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate xpath_reader;
+///
+/// use xpath_reader::Reader;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Kind {
+///     Article,
+///     Video,
+///     Other(String),
+/// }
+///
+/// from_xml_extensible_enum!(Kind, {
+///     "article" => Article,
+///     "video" => Video,
+/// }, Other);
+///
+/// # fn main() {
+/// let xml = r#"<?xml version="1.0"?><item kind="podcast"/>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let kind: Kind = reader.read("//@kind").unwrap();
+/// assert_eq!(kind, Kind::Other("podcast".to_string()));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_xml_extensible_enum {
+    ( $enum:ident, { $( $value:expr => $variant:ident ),* $(,)* }, $other:ident ) => {
+        impl $crate::FromXml for $enum {
+            fn from_xml<'d>(reader: &'d $crate::Reader<'d>) -> $crate::FromXmlResult<Self> {
+                let value: String = reader.read(".")?;
+                $(
+                    if value == $value {
+                        return Ok($enum::$variant);
+                    }
+                )*
+                Ok($enum::$other(value))
+            }
+        }
+    };
+}
+
+/// Implements `FromXml` for an enum with unit variants encoded as integer
+/// codes, like `<status>2</status>`.
+///
+/// The value is read as `i64` and mapped to the variant of its code. An
+/// unknown code results in an error naming the code.
+///
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate xpath_reader;
+///
+/// use xpath_reader::Reader;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Status {
+///     Pending,
+///     Done,
+/// }
+///
+/// from_xml_int_enum!(Status, {
+///     1 => Pending,
+///     2 => Done,
+/// });
+///
+/// # fn main() {
+/// let xml = r#"<?xml version="1.0"?><task><status>2</status></task>"#;
+/// let reader = Reader::from_str(xml, None).unwrap();
+/// let status: Status = reader.read("//status").unwrap();
+/// assert_eq!(status, Status::Done);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_xml_int_enum {
+    ( $enum:ident, { $( $code:expr => $variant:ident ),* $(,)* } ) => {
+        impl $crate::FromXml for $enum {
+            fn from_xml<'d>(reader: &'d $crate::Reader<'d>) -> $crate::FromXmlResult<Self> {
+                let code: i64 = reader.read(".")?;
+                $(
+                    if code == $code {
+                        return Ok($enum::$variant);
+                    }
+                )*
+                Err($crate::Error::__from_xml_msg(format!(
+                    "Unknown code {} for {}.",
+                    code,
+                    stringify!($enum)
+                )))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use {ErrorKind, FromXml, FromXmlResult, Reader};
+
+    #[derive(Debug, PartialEq)]
+    struct Circle {
+        r: f64,
+    }
+
+    impl FromXml for Circle {
+        fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+            Ok(Circle {
+                r: reader.read("@r")?,
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Square {
+        side: f64,
+    }
+
+    impl FromXml for Square {
+        fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+            Ok(Square {
+                side: reader.read("@side")?,
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Circle(Circle),
+        Square(Square),
+    }
+
+    from_xml_dispatch!(Shape, "type", {
+        "circle" => Circle(Circle),
+        "square" => Square(Square),
+    });
+
+    #[derive(Debug, PartialEq)]
+    enum Title {
+        Dc(String),
+        Atom(String),
+        Plain(String),
+    }
+
+    from_xml_element_dispatch!(Title, {
+        ("http://purl.org/dc/elements/1.1/", "title") => Dc(String),
+        ("http://www.w3.org/2005/Atom", "title") => Atom(String),
+        ("", "title") => Plain(String),
+    });
+
+    #[derive(Debug, PartialEq)]
+    struct Name(String);
+
+    #[derive(Debug, PartialEq)]
+    struct Pages(u32);
+
+    from_xml_newtype!(Name, String);
+    from_xml_newtype!(Pages, u32);
+
+    #[derive(Debug, PartialEq)]
+    enum Kind {
+        Article,
+        Video,
+        Other(String),
+    }
+
+    from_xml_extensible_enum!(Kind, {
+        "article" => Article,
+        "video" => Video,
+    }, Other);
+
+    #[derive(Debug, PartialEq)]
+    enum Status {
+        Pending,
+        Active,
+        Done,
+    }
+
+    from_xml_int_enum!(Status, {
+        0 => Pending,
+        1 => Active,
+        2 => Done,
+    });
+
+    #[test]
+    fn newtype() {
+        let xml = r#"<?xml version="1.0"?><book><name>Neuromancer</name><pages>271</pages></book>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read::<Name, _>("//name").unwrap(),
+            Name("Neuromancer".to_string())
+        );
+        assert_eq!(reader.read::<Pages, _>("//pages").unwrap(), Pages(271));
+        assert!(reader.read::<Pages, _>("//name").is_err());
+    }
+
+    #[test]
+    fn dispatch() {
+        let xml = r#"<?xml version="1.0"?>
+                     <shapes><shape type="circle" r="5"/><shape type="square" side="2"/>
+                     <shape type="triangle"/></shapes>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let shapes: Vec<Shape> = reader.read("//shape[@type != 'triangle']").unwrap();
+        assert_eq!(
+            shapes,
+            vec![
+                Shape::Circle(Circle { r: 5. }),
+                Shape::Square(Square { side: 2. }),
+            ]
+        );
+
+        let err = reader
+            .read::<Shape, _>("//shape[@type = 'triangle']")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn extensible_enum() {
+        let xml = r#"<?xml version="1.0"?>
+                     <items><kind>article</kind><kind>video</kind><kind>podcast</kind></items>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let kinds: Vec<Kind> = reader.read("//kind").unwrap();
+        assert_eq!(
+            kinds,
+            vec![
+                Kind::Article,
+                Kind::Video,
+                Kind::Other("podcast".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn int_enum() {
+        let xml = r#"<?xml version="1.0"?>
+                     <tasks><status>2</status><status>0</status><status>1</status><status>7</status></tasks>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let statuses: Vec<Status> = reader.read("//status[position() < 4]").unwrap();
+        assert_eq!(
+            statuses,
+            vec![Status::Done, Status::Pending, Status::Active]
+        );
+        let err = reader.read::<Status, _>("//status[4]").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(err.to_string().contains("Unknown code 7"));
+    }
+
+    #[test]
+    fn element_dispatch() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:atom="http://www.w3.org/2005/Atom">
+                     <dc:title>A</dc:title><atom:title>B</atom:title><title>C</title><dc:subject>D</dc:subject></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let titles: Vec<Title> = reader.read("/root/*[local-name() = 'title']").unwrap();
+        assert_eq!(
+            titles,
+            vec![
+                Title::Dc("A".to_string()),
+                Title::Atom("B".to_string()),
+                Title::Plain("C".to_string()),
+            ]
+        );
+        let err = reader.read::<Title, _>("/root/*[4]").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+}
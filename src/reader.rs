@@ -14,14 +14,28 @@
 
 //! XPath based document parsing.
 
+use context::Context;
 use errors::{Error, ErrorKind};
-use expression::XPathExpression;
+use expression::{escape_xpath_string, parse_xpath, XPathExpression};
+#[cfg(feature = "regex")]
+use regex::Regex;
+#[cfg(feature = "rust_decimal")]
+use rust_decimal::Decimal;
 use std::borrow::{Borrow, Cow};
-use sxd_document::Package;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+use std::str;
+use std::time::Duration;
 use sxd_document::parser::parse as sxd_parse;
-use sxd_xpath::{Context, Value, XPath};
+use sxd_document::Package;
 use sxd_xpath::nodeset::{Node, Nodeset};
-use util::Refable;
+use sxd_xpath::{OwnedQName, Value, XPath};
+use util::{expand_entities, expand_includes, nth_start_tag, Refable};
 
 /// Convenience redefinition of the FromXml result type.
 pub type FromXmlResult<T> = Result<T, Error>;
@@ -55,6 +69,16 @@ where
     fn from_xml_optional<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Option<Self>>;
 }
 
+/// A value that can check its own validity after being read.
+///
+/// This is used by `Reader::read_validated` to run checks spanning several
+/// fields, which can't be expressed in the `FromXml` implementation of a
+/// single field.
+pub trait ValidateXml {
+    /// Checks whether `self` is valid, returning an error if it is not.
+    fn validate(&self) -> Result<(), Error>;
+}
+
 impl<T> FromXml for T
 where
     T: FromXmlOptional,
@@ -78,9 +102,155 @@ where
     }
 }
 
+/// Checks whether the provided XML document is well-formed.
+///
+/// This only parses the document without constructing a `Reader`, the
+/// parse error is returned if the document is malformed. A document
+/// without a root element, e.g. an empty one or one consisting only of
+/// comments or processing instructions, results in an error of kind
+/// `ErrorKind::MissingValue`.
+pub fn is_well_formed(xml: &str) -> Result<(), Error> {
+    parse_xml(xml).map(|_| ())
+}
+
+fn parse_xml(xml: &str) -> Result<Package, Error> {
+    if is_empty_document(xml) {
+        return Err(Error::internal(
+            "Empty XML document, no root element found.",
+            ErrorKind::MissingValue,
+        ));
+    }
+    // TODO: Display all.
+    sxd_parse(xml).map_err(|e| Error::internal(format!("{}", e), ErrorKind::ParseXml))
+}
+
+/// Checks whether `xml` has no root element, i.e. contains nothing but a
+/// byte order mark, whitespace, the XML declaration, comments and
+/// processing instructions.
+fn is_empty_document(xml: &str) -> bool {
+    let mut rest = xml.trim_start_matches('\u{feff}');
+    loop {
+        rest = rest.trim_start();
+        let end = if rest.starts_with("<?") {
+            rest.find("?>").map(|end| end + 2)
+        } else if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else {
+            return rest.is_empty();
+        };
+        match end {
+            Some(end) => rest = &rest[end..],
+            None => return false,
+        }
+    }
+}
+
+/// Returns the version from the XML declaration at the start of `xml`.
+fn declared_xml_version(xml: &str) -> Option<&str> {
+    let declaration = xml.trim_start_matches('\u{feff}').strip_prefix("<?xml")?;
+    if !declaration.starts_with(|c: char| c.is_ascii_whitespace()) {
+        return None;
+    }
+    let declaration = &declaration[..declaration.find("?>")?];
+    let rest = declaration.split_once("version")?.1.trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let rest = &rest[1..];
+    rest.find(quote).map(|end| &rest[..end])
+}
+
+fn single_nodeset(node: Node) -> Nodeset {
+    let mut nodeset = Nodeset::new();
+    nodeset.add(node);
+    nodeset
+}
+
+/// Parses an ISO-8601 duration consisting of days, hours, minutes and
+/// seconds, like `P1DT2H30M15.5S`.
+fn parse_iso_duration(value: &str) -> Result<Duration, String> {
+    let rest = value
+        .strip_prefix('P')
+        .ok_or_else(|| "Duration must start with 'P'".to_string())?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    if (date.is_empty() && time.is_none()) || time == Some("") {
+        return Err("Duration has no components".to_string());
+    }
+
+    let mut duration = Duration::from_secs(0);
+    for &(part, designators) in &[(date, "D"), (time.unwrap_or(""), "HMS")] {
+        let mut allowed = designators;
+        let mut start = 0;
+        for (i, c) in part.char_indices() {
+            if c.is_ascii_digit() || c == '.' {
+                continue;
+            }
+            let number = &part[start..i];
+            start = i + c.len_utf8();
+            let pos = match allowed.find(c) {
+                Some(pos) => pos,
+                None if designators == "D" && "YMW".contains(c) => {
+                    return Err(format!("Unsupported duration component '{}'", c));
+                }
+                None => return Err(format!("Unexpected duration component '{}'", c)),
+            };
+            allowed = &allowed[pos + 1..];
+
+            let component = if c == 'S' {
+                number
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| secs.is_finite() && *secs >= 0.0 && *secs < u64::MAX as f64)
+                    .map(Duration::from_secs_f64)
+            } else {
+                let factor = match c {
+                    'D' => 86_400,
+                    'H' => 3_600,
+                    _ => 60,
+                };
+                number
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|n| n.checked_mul(factor))
+                    .map(Duration::from_secs)
+            };
+            duration = component
+                .and_then(|component| duration.checked_add(component))
+                .ok_or_else(|| format!("Invalid duration component '{}{}'", number, c))?;
+        }
+        if start != part.len() {
+            return Err(format!("Missing designator after '{}'", &part[start..]));
+        }
+    }
+    Ok(duration)
+}
+
+impl<'d> fmt::Debug for Reader<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let anchor_node = match self.anchor {
+            Anchor::Root(_) => Some("/".to_string()),
+            Anchor::Nodeset(ref nodeset) => nodeset
+                .document_order_first()
+                .map(|n| n.prefixed_name().unwrap_or_else(|| format!("{:?}", n))),
+        };
+        f.debug_struct("Reader")
+            .field("context", &self.context)
+            .field("anchor_size", &self.anchor_nodeset().size())
+            .field("anchor_node", &anchor_node)
+            .field("depth", &self.depth)
+            .field("max_depth", &self.max_depth)
+            .finish()
+    }
+}
+
+const ASCII_LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const ASCII_UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
 enum Anchor<'d> {
     Nodeset(Nodeset<'d>),
-    Root(Package),
+    Root(Box<Package>),
 }
 
 /// XML element tree reader using XPath expressions.
@@ -96,228 +266,2791 @@ enum Anchor<'d> {
 ///    node in the nodeset, in document order.
 /// 2) `FromXml` implementors can query the anchor nodeset to convert
 ///    multiple nodes into a single target value.
+///
+/// # Expression cache
+///
+/// XPath expressions passed as strings are parsed only once and then
+/// cached. The cache is shared between a reader and all readers derived
+/// from it, so repeatedly reading the same expressions in a loop does not
+/// parse them again. Expressions built from document data, e.g. attribute
+/// values, can make the number of distinct expressions grow with the
+/// document, so the cache is cleared once it holds 256 entries.
+///
+/// # Nesting depth
+///
+/// Every reader created by evaluating an expression relative to another
+/// reader is one level deeper than its parent. This holds for readers
+/// anchored at the whole result, like the ones of `with_nodeset_eval`, as
+/// well as for readers anchored at single nodes of the result, like the
+/// ones of `nodes` or `child_readers`. Only the readers which `FromXml`
+/// implementations for collections create for each node of their own
+/// anchor nodeset stay on the same level. A maximum depth can be set
+/// with `set_max_depth` to guard against stack overflows when reading
+/// recursive structures from untrusted documents.
 pub struct Reader<'d> {
     context: Refable<'d, Context<'d>>,
     anchor: Anchor<'d>,
+    depth: usize,
+    max_depth: Option<usize>,
+    cache: ExpressionCache,
+    source: Option<Rc<str>>,
+    xml_version: Option<Box<str>>,
 }
 
+type ExpressionCache = Rc<RefCell<HashMap<String, Rc<XPath>>>>;
+
+/// The number of parsed expressions at which the expression cache is
+/// cleared.
+const MAX_CACHED_EXPRESSIONS: usize = 256;
+
 impl<'d> Reader<'d> {
     /// Read the result of the XPath expression into a value of type `V`.
+    ///
+    /// If the expression evaluates to a string, number or boolean instead
+    /// of a nodeset, like `substring-before(//url, '?')`, the value is read
+    /// from its XPath string representation, i.e. as if it was the text of
+    /// a single node.
+    ///
+    /// For such scalar results `V::from_xml` is passed a reader anchored at
+    /// the root of a separate document `<value>...</value>`, which contains
+    /// nothing but the string. So in `FromXml` implementations that reader
+    /// reports `is_root() == true`, `package` returns the separate document,
+    /// and all further expressions, relative or absolute, are evaluated
+    /// against it rather than against the original document.
     pub fn read<'a, V, X>(&'d self, xpath_expr: X) -> Result<V, Error>
     where
         V: FromXml,
         X: Into<XPathExpression<'a>>,
     {
-        let reader = self.with_nodeset_eval(xpath_expr)?;
+        let reader = match self.evaluate(xpath_expr)? {
+            Value::Nodeset(nodeset) => self.nested(nodeset)?,
+            scalar => self.scalar_reader(&scalar.string()),
+        };
         V::from_xml(&reader)
     }
 
-    /// Construct a new reader for the specified XML document.
+    /// Read the result of the XPath expression into a value of type `V`,
+    /// adding the context `while reading field '<field_name>'` to errors.
     ///
-    /// A context can be specified to define custom functions,
-    /// variables and namespaces.
-    pub fn from_str(xml: &str, context: Option<&'d Context<'d>>) -> Result<Self, Error> {
-        // TODO: Display all.
-        let package = sxd_parse(xml)
-            .map_err(|e| Error::internal(format!("{}", e.1[0]), ErrorKind::ParseXml))?;
+    /// This makes errors from deeply nested structures easier to locate,
+    /// see `Error::with_context`.
+    pub fn read_ctx<'a, V, X>(&'d self, xpath_expr: X, field_name: &str) -> Result<V, Error>
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        self.read(xpath_expr)
+            .map_err(|e| e.with_context(format!("while reading field '{}'", field_name)))
+    }
 
-        let context_refable = match context {
-            Some(c) => Refable::Borrowed(c),
-            None => Refable::Owned(Context::default()),
-        };
+    /// Read the result of the XPath expression into a value of type `V` and
+    /// validate it with `ValidateXml::validate`.
+    ///
+    /// Validation errors are returned with the XPath expression recorded,
+    /// see `Error::expression`.
+    pub fn read_validated<'a, V, X>(&'d self, xpath_expr: X) -> Result<V, Error>
+    where
+        V: FromXml + ValidateXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath_expr = xpath_expr.into();
+        let value: V = self.read(&xpath_expr)?;
+        value
+            .validate()
+            .map_err(|e| e.with_expression(xpath_expr.to_string()))?;
+        Ok(value)
+    }
 
-        Ok(Reader {
-            context: context_refable,
-            anchor: Anchor::Root(package),
-        })
+    /// Read the result of the XPath expression into a value of type `V` and
+    /// transform it with `f`.
+    pub fn read_map_value<'a, V, T, F, X>(&'d self, xpath_expr: X, f: F) -> Result<T, Error>
+    where
+        V: FromXml,
+        F: FnOnce(V) -> T,
+        X: Into<XPathExpression<'a>>,
+    {
+        self.read(xpath_expr).map(f)
     }
 
-    /// Construct a new reader for the specified nodeset.
+    /// Reads the string value of the first node selected by the XPath
+    /// expression, or `default` if no node is selected.
     ///
-    /// Relative XPath expressions will then resolve to the first node
-    /// in the nodeset.
+    /// An empty node is not treated specially, so its empty string value
+    /// is returned rather than the default.
+    pub fn read_string_or<'a, X>(&'d self, xpath_expr: X, default: &str) -> Result<String, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.evaluate_nodeset(xpath_expr)?;
+        Ok(nodes
+            .document_order_first()
+            .map(|n| n.string_value())
+            .unwrap_or_else(|| default.to_string()))
+    }
+
+    /// Reads the string value of the first node selected by the XPath
+    /// expression, split into lines.
     ///
-    /// The nodeset can be obtained from a `Reader<'d>` with the
-    /// `anchor_nodeset` and `anchor_node` methods.
-    /// A context can be specified to define custom functions,
-    /// variables and namespaces.
+    /// Lines are split on `\n` and `\r\n`, the line endings are not
+    /// included. Empty lines are preserved, except that a single trailing
+    /// line ending does not produce an empty last line, so `"a\n\nb\n"`
+    /// results in `["a", "", "b"]`.
+    pub fn read_lines<'a, X>(&'d self, xpath_expr: X) -> Result<Vec<String>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        Ok(value.lines().map(str::to_string).collect())
+    }
+
+    /// Reads the trimmed string value of the first of the XPath expressions
+    /// `xpath_exprs` whose value is not empty after trimming.
     ///
-    /// Note: The nodeset can even be empty, which can be used by `FromXml`
-    /// implementors to cover the absence of a value in some cases.
-    pub fn from_nodeset(nodeset: Nodeset<'d>, context: Option<&'d Context<'d>>) -> Self {
-        let context_refable = match context {
-            Some(c) => Refable::Borrowed(c),
-            None => Refable::Owned(Context::default()),
-        };
+    /// This is useful for fallback content, like a summary which is used if
+    /// the description is empty. If all values are empty or absent, an
+    /// error of kind `ErrorKind::MissingValue` is returned.
+    pub fn read_first_nonempty(&'d self, xpath_exprs: &[&str]) -> Result<String, Error> {
+        for xpath_expr in xpath_exprs {
+            let value = self.evaluate(*xpath_expr)?.string();
+            let trimmed = value.trim();
+            if !trimmed.is_empty() {
+                return Ok(trimmed.to_string());
+            }
+        }
+        Err(Error::internal(
+            format!("All of {:?} are empty or absent.", xpath_exprs),
+            ErrorKind::MissingValue,
+        ))
+    }
 
-        Reader {
-            context: context_refable,
-            anchor: Anchor::Nodeset(nodeset),
+    /// Returns an iterator over readers anchored at each node of the XPath
+    /// expression's result, in document order.
+    ///
+    /// The expression is evaluated immediately, but the readers are only
+    /// created while iterating, so the result can be processed with the
+    /// usual iterator adaptors without collecting it first.
+    pub fn nodes<'a, X>(
+        &'d self,
+        xpath_expr: X,
+    ) -> Result<impl Iterator<Item = Reader<'d>> + 'd, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.evaluate_nodeset(xpath_expr)?.document_order();
+        let depth = self.nested_depth()?;
+        Ok(nodes
+            .into_iter()
+            .map(move |node| self.derived(single_nodeset(node), depth)))
+    }
+
+    /// Read each node of the XPath expression's result into a value of type `V`.
+    ///
+    /// In contrast to reading a `Vec<V>` this does not fail on the first
+    /// node which can't be read, instead there is one result per node in
+    /// document order. Only the evaluation of the expression itself can
+    /// make the whole method fail.
+    pub fn read_vec_results<'a, V, X>(
+        &'d self,
+        xpath_expr: X,
+    ) -> Result<Vec<Result<V, Error>>, Error>
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.with_nodeset_eval(xpath_expr)?;
+        let results = nodes
+            .anchor_nodeset()
+            .document_order()
+            .iter()
+            .map(|node| {
+                let reader = nodes.node_reader(*node);
+                V::from_xml(&reader)
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Read each node of the XPath expression's result into a value of type
+    /// `V` and append it to `out`.
+    ///
+    /// This allows collecting the results of multiple expressions into one
+    /// collection without intermediate allocations. If a node can't be read
+    /// the error is returned, the values read before remain in `out`.
+    pub fn read_into<'a, C, V, X>(&'d self, xpath_expr: X, out: &mut C) -> Result<(), Error>
+    where
+        C: Extend<V>,
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.with_nodeset_eval(xpath_expr)?;
+        for node in nodes.anchor_nodeset().document_order() {
+            let reader = nodes.node_reader(node);
+            out.extend(Some(V::from_xml(&reader)?));
         }
+        Ok(())
     }
 
-    /// Convenience method over `from_nodeset` when there is only one `Node` for
-    /// the nodeset.
-    pub fn from_node(node: Node<'d>, context: Option<&'d Context<'d>>) -> Self {
-        let mut nodeset = Nodeset::new();
-        nodeset.add(node);
-        Self::from_nodeset(nodeset, context)
+    /// Read all nodes of the XPath expression's result into values of type
+    /// `V`, sorted by the key returned by `key`.
+    ///
+    /// The sort is stable, so values with equal keys stay in document order.
+    pub fn read_vec_sorted_by<'a, V, K, F, X>(
+        &'d self,
+        xpath_expr: X,
+        key: F,
+    ) -> Result<Vec<V>, Error>
+    where
+        V: FromXml,
+        K: Ord,
+        F: Fn(&V) -> K,
+        X: Into<XPathExpression<'a>>,
+    {
+        let mut values: Vec<V> = self.read(xpath_expr)?;
+        values.sort_by_key(key);
+        Ok(values)
     }
 
-    /// Creates a new `Reader` instance by evaluating an XPath expression and
-    /// using the result nodeset as anchor nodeset.
+    /// Read at most `max` nodes of the XPath expression's result into values
+    /// of type `V`.
     ///
-    /// The current context will be passed to the new reader.
-    pub fn with_nodeset_eval<'a, X>(&'d self, xpath_expr: X) -> Result<Self, Error>
+    /// This bounds the work done for untrusted documents. If more than `max`
+    /// nodes are selected, the result is truncated to the first `max` nodes
+    /// in document order if `truncate` is `true`, otherwise an error of kind
+    /// `ErrorKind::LimitExceeded` is returned.
+    pub fn read_vec_limited<'a, V, X>(
+        &'d self,
+        xpath_expr: X,
+        max: usize,
+        truncate: bool,
+    ) -> Result<Vec<V>, Error>
     where
+        V: FromXml,
         X: Into<XPathExpression<'a>>,
     {
         let xpath = xpath_expr.into();
-        match self.evaluate(&xpath)? {
-            Value::Nodeset(nodeset) => Ok(Reader {
-                context: self.context.clone_ref(),
-                anchor: Anchor::Nodeset(nodeset),
-            }),
-            _ => Err(Error::internal(
+        let nodes = self.with_nodeset_eval(&xpath)?;
+        let nodeset = nodes.anchor_nodeset().document_order();
+        if nodeset.len() > max && !truncate {
+            return Err(Error::internal(
                 format!(
-                    "XPath expression did not evaluate to nodeset: '{}'",
-                    xpath.to_string()
+                    "XPath expression '{}' evaluated to {} nodes, the limit is {}.",
+                    xpath,
+                    nodeset.len(),
+                    max
                 ),
-                ErrorKind::EvalXPath,
-            )),
+                ErrorKind::LimitExceeded,
+            ));
         }
+        nodeset
+            .into_iter()
+            .take(max)
+            .map(|node| V::from_xml(&nodes.node_reader(node)))
+            .collect()
     }
 
-    /// References the evaluation context of this Reader.
-    pub fn context(&'d self) -> &'d Context<'d> {
-        self.context.borrow()
-    }
-
-    /// Returns the anchor nodeset of the current reader.
-    pub fn anchor_nodeset(&'d self) -> Cow<Nodeset<'d>> {
-        match self.anchor {
-            Anchor::Nodeset(ref nodeset) => Cow::Borrowed(nodeset),
-            Anchor::Root(ref package) => {
-                let mut nodeset = Nodeset::new();
-                let root = package.as_document().root().clone();
-                nodeset.add(Node::Root(root));
-                Cow::Owned(nodeset)
-            }
-        }
+    /// Read the string values of all nodes of the XPath expression's result,
+    /// sharing one allocation for equal values.
+    ///
+    /// Values already contained in `interner` are reused, new values are
+    /// added to it. Passing the same interner to multiple calls saves memory
+    /// for highly repetitive data, like category names.
+    pub fn read_interned_vec<'a, X>(
+        &'d self,
+        xpath_expr: X,
+        interner: &mut HashSet<Rc<str>>,
+    ) -> Result<Vec<Rc<str>>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.evaluate_nodeset(xpath_expr)?;
+        let values = nodes
+            .document_order()
+            .into_iter()
+            .map(|node| {
+                let value = node.string_value();
+                if let Some(interned) = interner.get(value.as_str()) {
+                    return interned.clone();
+                }
+                let interned: Rc<str> = Rc::from(value);
+                interner.insert(interned.clone());
+                interned
+            })
+            .collect();
+        Ok(values)
     }
 
-    /// Returns the first (in document order) node in the anchor nodeset.
+    /// Read each node of the XPath expression's result with `f`, which gets
+    /// passed the local name of the node and a reader anchored at it.
     ///
-    /// If the anchor nodeset is empty, `None` will be returned.
-    pub fn anchor_node(&'d self) -> Option<Node<'d>> {
-        match self.anchor {
-            Anchor::Nodeset(ref nodeset) => nodeset.document_order_first(),
-            Anchor::Root(ref package) => Some(package.as_document().root().clone().into()),
-        }
+    /// This allows reading heterogeneous sequences, e.g. into a vector of
+    /// an enum with one variant per element name, while preserving the
+    /// document order. Nodes without a name, like text nodes, are passed
+    /// an empty name.
+    pub fn read_dispatched_vec<'a, T, F, X>(
+        &'d self,
+        xpath_expr: X,
+        mut f: F,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&str, &Reader) -> Result<T, Error>,
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.evaluate_nodeset(xpath_expr)?;
+        nodes
+            .document_order()
+            .into_iter()
+            .map(|node| {
+                let name = node.expanded_name().map(|n| n.local_part()).unwrap_or("");
+                f(name, &self.nested_node(node)?)
+            })
+            .collect()
     }
 
-    fn evaluate<'a, X>(&'d self, xpath_expr: X) -> Result<Value<'d>, Error>
+    /// Evaluates the XPath expression relative to the anchor node and
+    /// converts the result to a boolean.
+    ///
+    /// The XPath conversion rules apply, i.e. a nodeset is `true` if it is
+    /// not empty, so `active` tests for an `<active>` child, and
+    /// `@priority > 3 and not(@disabled)` works as expected.
+    pub fn matches<'a, X>(&'d self, predicate_xpath_expr: X) -> Result<bool, Error>
     where
         X: Into<XPathExpression<'a>>,
     {
-        let xpath_expr = xpath_expr.into();
-        let xpath = xpath_expr.parsed()?;
-        // TODO: Error message.
-        let anchor = self.anchor_node().ok_or_else(|| {
-            let xpath_ref: &XPath = xpath.borrow();
-            Error::internal(
-                format!("Anchor node not found when evaluating: {:?}", xpath_ref),
-                ErrorKind::EvalXPath,
-            )
-        })?;
+        Ok(self.evaluate(predicate_xpath_expr)?.boolean())
+    }
 
-        // Note: This is very ugly but otherwise does not compile.
-        let xpath_ref: &XPath = xpath.borrow();
-        xpath_ref
-            .evaluate(self.context.borrow(), anchor)
-            .map_err(|e| Error::internal(format!("{}", e), ErrorKind::EvalXPath))
+    /// Returns whether the anchor node has the attribute `name`, regardless
+    /// of its value.
+    pub fn read_attr_present(&'d self, name: &str) -> Result<bool, Error> {
+        let xpath = format!("boolean(@{})", name);
+        Ok(self.evaluate(xpath.as_str())?.boolean())
     }
-}
 
-impl FromXml for String {
-    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
-        reader
-            .anchor_node()
-            .ok_or(Error::custom_msg("Missing (anchor) node."))
-            .map(|n| n.string_value())
+    /// Read the attribute `name` of the anchor node into an optional value.
+    ///
+    /// `None` is returned both if the attribute is absent and if its value
+    /// is empty or consists only of whitespace.
+    pub fn read_option_attr<V>(&'d self, name: &str) -> Result<Option<V>, Error>
+    where
+        Option<V>: FromXml,
+    {
+        let xpath = format!("@{}", name);
+        let attr = self.with_nodeset_eval(xpath.as_str())?;
+        match attr.anchor_node() {
+            Some(node) if !node.string_value().trim().is_empty() => Option::<V>::from_xml(&attr),
+            _ => Ok(None),
+        }
     }
-}
 
-impl FromXml for Option<String> {
-    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
-        Ok(reader.anchor_node().and_then(|node| {
-            let s = node.string_value();
-            if s.is_empty() {
-                None
-            } else {
-                Some(s)
+    /// Read the attribute `name` of the anchor node, or `default` if the
+    /// attribute is absent or empty.
+    ///
+    /// See `read_option_attr` for when an attribute is considered empty.
+    pub fn read_attr_or<V>(&'d self, name: &str, default: V) -> Result<V, Error>
+    where
+        Option<V>: FromXml,
+    {
+        Ok(self.read_option_attr(name)?.unwrap_or(default))
+    }
+
+    /// Returns which one of the child elements `names` is present on the
+    /// anchor node.
+    ///
+    /// This is useful for choice groups, where exactly one of several
+    /// elements is expected. If none of them is present an error of kind
+    /// `ErrorKind::MissingValue` is returned, if more than one is present
+    /// the error is of kind `ErrorKind::FromXml`.
+    pub fn read_present_one_of(&'d self, names: &[&str]) -> Result<String, Error> {
+        let mut present = Vec::new();
+        for name in names {
+            if self.with_nodeset_eval(*name)?.anchor_node().is_some() {
+                present.push(*name);
             }
-        }))
+        }
+        match present.len() {
+            1 => Ok(present[0].to_string()),
+            0 => Err(Error::internal(
+                format!("None of the elements {:?} is present.", names),
+                ErrorKind::MissingValue,
+            )),
+            _ => Err(Error::internal(
+                format!("Only one of the elements {:?} may be present.", present),
+                ErrorKind::FromXml,
+            )),
+        }
     }
-}
 
-impl<T> FromXml for Vec<T>
-where
-    T: FromXml,
-{
-    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
-        reader
-            .anchor_nodeset()
+    /// Read a key and a value from each node of the XPath expression's
+    /// result.
+    ///
+    /// `key_xpath` and `value_xpath` are evaluated relative to each node.
+    /// In contrast to reading a map, the pairs are returned in document
+    /// order and duplicate keys are retained.
+    pub fn read_pairs<K, V>(
+        &'d self,
+        node_xpath: &str,
+        key_xpath: &str,
+        value_xpath: &str,
+    ) -> Result<Vec<(K, V)>, Error>
+    where
+        K: FromXml,
+        V: FromXml,
+    {
+        let nodes = self.evaluate_nodeset(node_xpath)?;
+        nodes
             .document_order()
-            .iter()
-            .map(|node| {
-                let reader = Reader::from_node(*node, Some(reader.context()));
-                T::from_xml(&reader)
-            })
+            .into_iter()
+            .map(|node| self.nested_node(node)?.read_kv(key_xpath, value_xpath))
             .collect()
     }
-}
 
-macro_rules! from_parse_str {
-    ( $( $type:ty ),* ) => {
-        $(
-            impl FromXml for $type {
-                fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error>
-                {
-                    let s = String::from_xml(reader)?;
-                    s.parse::<$type>().map_err(|e| Error::custom_err(e))
-                }
-            }
+    /// Read a map from the keys and values of each node of the XPath
+    /// expression's result.
+    ///
+    /// `key_xpath` and `value_xpath` are evaluated relative to each node,
+    /// the values are read into type `V`. If a key occurs more than once,
+    /// the value of its last occurrence is kept, see `read_pairs` for
+    /// retaining all of them.
+    pub fn read_typed_map<V>(
+        &'d self,
+        node_xpath: &str,
+        key_xpath: &str,
+        value_xpath: &str,
+    ) -> Result<HashMap<String, V>, Error>
+    where
+        V: FromXml,
+    {
+        let pairs = self.read_pairs(node_xpath, key_xpath, value_xpath)?;
+        Ok(pairs.into_iter().collect())
+    }
 
-            impl FromXml for Option<$type> {
-                fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error>
-                {
-                    if let Some(s) = Option::<String>::from_xml(reader)? {
-                        Ok(Some(s.parse::<$type>().map_err(|e| Error::custom_err(e))?))
+    /// Read a key and a value relative to the anchor node.
+    ///
+    /// This reads one entry of a pairwise structure like
+    /// `<entry><k>color</k><v>red</v></entry>`, see `read_pairs` for reading
+    /// all entries at once.
+    pub fn read_kv<K, V>(&'d self, key_xpath: &str, value_xpath: &str) -> Result<(K, V), Error>
+    where
+        K: FromXml,
+        V: FromXml,
+    {
+        Ok((self.read(key_xpath)?, self.read(value_xpath)?))
+    }
+
+    /// Collects the values `map` returns for the local names of the child
+    /// elements of the anchor node into a set.
+    ///
+    /// This is useful for flags encoded by element presence, like
+    /// `<permissions><read/><write/></permissions>`. If `map` returns `None`
+    /// for an element, it is skipped if `ignore_unknown` is set, otherwise
+    /// an error of kind `ErrorKind::FromXml` is returned.
+    pub fn read_element_set<T, F>(
+        &'d self,
+        map: F,
+        ignore_unknown: bool,
+    ) -> Result<HashSet<T>, Error>
+    where
+        T: Eq + Hash,
+        F: Fn(&str) -> Option<T>,
+    {
+        let mut set = HashSet::new();
+        for child in self.evaluate_nodeset("*")?.document_order() {
+            let name = child.expanded_name().map(|n| n.local_part()).unwrap_or("");
+            match map(name) {
+                Some(value) => {
+                    set.insert(value);
+                }
+                None if ignore_unknown => {}
+                None => {
+                    return Err(Error::internal(
+                        format!("Unknown element '{}'.", name),
+                        ErrorKind::FromXml,
+                    ))
+                }
+            }
+        }
+        Ok(set)
+    }
+
+    /// Read a list of values, distinguishing an absent container from an
+    /// empty one.
+    ///
+    /// If `container_xpath` selects no node `None` is returned, otherwise
+    /// the nodes selected by `item_xpath` relative to the (first) container
+    /// are read into the returned vector, which can be empty.
+    pub fn read_option_vec<'a, 'b, V, X, Y>(
+        &'d self,
+        container_xpath: X,
+        item_xpath: Y,
+    ) -> Result<Option<Vec<V>>, Error>
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+        Y: Into<XPathExpression<'b>>,
+    {
+        let container = self.with_nodeset_eval(container_xpath)?;
+        if container.anchor_node().is_none() {
+            return Ok(None);
+        }
+        container.read(item_xpath).map(Some)
+    }
+
+    /// Read the attribute `name` of the anchor node into a value of type `V`.
+    ///
+    /// If the attribute is absent an error of kind `ErrorKind::MissingValue`
+    /// is returned.
+    pub fn read_required_attr<V>(&'d self, name: &str) -> Result<V, Error>
+    where
+        V: FromXml,
+    {
+        let xpath = format!("@{}", name);
+        let attr = self.with_nodeset_eval(xpath.as_str())?;
+        if attr.anchor_node().is_none() {
+            return Err(Error::internal(
+                format!("Missing required attribute '{}'.", name),
+                ErrorKind::MissingValue,
+            ));
+        }
+        V::from_xml(&attr)
+    }
+
+    /// Read the attribute `name` of the anchor node together with the node's
+    /// string value.
+    ///
+    /// This is a shortcut for the common pairing of an identifier and a
+    /// label, like `<item id="5">Widget</item>`. If the attribute is absent
+    /// an error of kind `ErrorKind::MissingValue` is returned.
+    pub fn read_attr_and_text<A>(&'d self, name: &str) -> Result<(A, String), Error>
+    where
+        A: FromXml,
+    {
+        Ok((self.read_required_attr(name)?, String::from_xml(self)?))
+    }
+
+    /// Read the attribute `name` of the element selected by
+    /// `element_xpath_expr`.
+    ///
+    /// This is a shortcut for `with_nodeset_eval` followed by
+    /// `read_required_attr`. If no element is selected or it does not have
+    /// the attribute, an error of kind `ErrorKind::MissingValue` is
+    /// returned.
+    pub fn read_attr_at<'a, V, X>(&'d self, element_xpath_expr: X, name: &str) -> Result<V, Error>
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let element_xpath_expr = element_xpath_expr.into();
+        let description = element_xpath_expr.to_string();
+        let element = self.with_nodeset_eval(element_xpath_expr)?;
+        if element.anchor_node().is_none() {
+            return Err(Error::internal(
+                format!("Element '{}' not found.", description),
+                ErrorKind::MissingValue,
+            )
+            .with_expression(description));
+        }
+        element.read_required_attr(name)
+    }
+
+    /// Read the element selected by `xpath_expr` only if its attribute
+    /// `name` equals `expected`.
+    ///
+    /// If no element is selected, or its attribute is missing or has a
+    /// different value, `None` is returned.
+    pub fn read_if_attr<'a, V, X>(
+        &'d self,
+        xpath_expr: X,
+        name: &str,
+        expected: &str,
+    ) -> Result<Option<V>, Error>
+    where
+        V: FromXml,
+        X: Into<XPathExpression<'a>>,
+    {
+        let element = self.with_nodeset_eval(xpath_expr)?;
+        let matches = element
+            .anchor_node()
+            .and_then(|node| node.element())
+            .and_then(|e| e.attribute_value(name))
+            == Some(expected);
+        if matches {
+            V::from_xml(&element).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Construct a new reader for the specified XML document.
+    ///
+    /// A context can be specified to define custom functions,
+    /// variables and namespaces.
+    ///
+    /// If the document has no root element, i.e. contains nothing but
+    /// whitespace, the XML declaration, comments or processing
+    /// instructions, an error of kind `ErrorKind::MissingValue` is returned.
+    /// Other malformed documents result in an error of kind
+    /// `ErrorKind::ParseXml`.
+    pub fn from_str(xml: &str, context: Option<&'d Context<'d>>) -> Result<Self, Error> {
+        Self::parse(xml, context, false)
+    }
+
+    /// Construct a new reader for the specified XML document, retaining a
+    /// copy of the source text.
+    ///
+    /// This is like `from_str`, but enables `source_line` for this reader
+    /// and all readers derived from it, at the cost of keeping the source
+    /// text in memory while any of them are alive.
+    pub fn from_str_with_source(
+        xml: &str,
+        context: Option<&'d Context<'d>>,
+    ) -> Result<Self, Error> {
+        Self::parse(xml, context, true)
+    }
+
+    fn parse(
+        xml: &str,
+        context: Option<&'d Context<'d>>,
+        retain_source: bool,
+    ) -> Result<Self, Error> {
+        let package = parse_xml(xml)?;
+
+        let context_refable = match context {
+            Some(c) => Refable::Borrowed(c),
+            None => Refable::Owned(Context::default()),
+        };
+
+        Ok(Reader {
+            context: context_refable,
+            anchor: Anchor::Root(Box::new(package)),
+            depth: 0,
+            max_depth: None,
+            cache: ExpressionCache::default(),
+            source: if retain_source {
+                Some(xml.into())
+            } else {
+                None
+            },
+            xml_version: Some(declared_xml_version(xml).unwrap_or("1.0").into()),
+        })
+    }
+
+    /// Construct a new reader for the specified XML document, expanding
+    /// custom entities before parsing.
+    ///
+    /// `entities` maps entity names to their replacement text, so a
+    /// reference like `&custom;` is replaced by `entities["custom"]`.
+    /// The substitution is textual and happens before the document is
+    /// parsed. Character references and the predefined XML entities are
+    /// left as they are, any other undefined entity results in an error of
+    /// kind `ErrorKind::ParseXml`. References inside of comments, CDATA
+    /// sections and processing instructions are kept literally.
+    pub fn from_str_with_entities(
+        xml: &str,
+        entities: &HashMap<String, String>,
+        context: Option<&'d Context<'d>>,
+    ) -> Result<Self, Error> {
+        let xml = expand_entities(xml, entities)?;
+        Self::from_str(&xml, context)
+    }
+
+    /// Construct a new reader for the specified XML document, resolving
+    /// XInclude elements before parsing.
+    ///
+    /// Every `include` element of the XInclude namespace
+    /// (`http://www.w3.org/2001/XInclude`) is replaced by the document
+    /// fragment `resolver` returns for its `href` attribute. Fragments are
+    /// expanded recursively and circular includes result in an error of
+    /// kind `ErrorKind::ParseXml`. Other XInclude features like `parse="text"`,
+    /// `xpointer` or fallbacks are not supported.
+    ///
+    /// Only `include` elements with a prefix bound to the XInclude namespace
+    /// (e.g. `xmlns:xi="http://www.w3.org/2001/XInclude"`) are recognised,
+    /// elements in the XInclude default namespace are left untouched. Includes
+    /// inside comments, CDATA sections and processing instructions are
+    /// ignored.
+    pub fn from_str_with_includes<R>(
+        xml: &str,
+        resolver: R,
+        context: Option<&'d Context<'d>>,
+    ) -> Result<Self, Error>
+    where
+        R: Fn(&str) -> Result<String, Error>,
+    {
+        let xml = expand_includes(xml, &resolver)?;
+        Self::from_str(&xml, context)
+    }
+
+    /// Construct a new reader for the specified XML document provided as bytes.
+    ///
+    /// The bytes have to be valid UTF-8, otherwise an error of kind
+    /// `ErrorKind::ParseXml` is returned.
+    pub fn from_bytes(xml: &[u8], context: Option<&'d Context<'d>>) -> Result<Self, Error> {
+        let xml = str::from_utf8(xml).map_err(|e| Error::internal(e, ErrorKind::ParseXml))?;
+        Self::from_str(xml, context)
+    }
+
+    /// Construct a new reader for the specified nodeset.
+    ///
+    /// Relative XPath expressions will then resolve to the first node
+    /// in the nodeset.
+    ///
+    /// The nodeset can be obtained from a `Reader<'d>` with the
+    /// `anchor_nodeset` and `anchor_node` methods.
+    /// A context can be specified to define custom functions,
+    /// variables and namespaces.
+    ///
+    /// Note: The nodeset can even be empty, which can be used by `FromXml`
+    /// implementors to cover the absence of a value in some cases.
+    pub fn from_nodeset(nodeset: Nodeset<'d>, context: Option<&'d Context<'d>>) -> Self {
+        let context_refable = match context {
+            Some(c) => Refable::Borrowed(c),
+            None => Refable::Owned(Context::default()),
+        };
+
+        Reader {
+            context: context_refable,
+            anchor: Anchor::Nodeset(nodeset),
+            depth: 0,
+            max_depth: None,
+            cache: ExpressionCache::default(),
+            source: None,
+            xml_version: None,
+        }
+    }
+
+    /// Convenience method over `from_nodeset` when there is only one `Node` for
+    /// the nodeset.
+    pub fn from_node(node: Node<'d>, context: Option<&'d Context<'d>>) -> Self {
+        Self::from_nodeset(single_nodeset(node), context)
+    }
+
+    /// Creates a new `Reader` instance by evaluating an XPath expression and
+    /// using the result nodeset as anchor nodeset.
+    ///
+    /// The current context will be passed to the new reader.
+    pub fn with_nodeset_eval<'a, X>(&'d self, xpath_expr: X) -> Result<Self, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodeset = self.evaluate_nodeset(xpath_expr)?;
+        self.nested(nodeset)
+    }
+
+    /// Creates a new `Reader` anchored at the base path `base_xpath_expr`,
+    /// to scope multiple reads sharing a common ancestor.
+    ///
+    /// This is the idiomatic way to avoid repeating the base path in every
+    /// expression: Reading `title` and `author` from
+    /// `reader.with_base("/library/book[1]")` is equivalent to reading
+    /// `/library/book[1]/title` and `/library/book[1]/author`. In contrast
+    /// to `with_nodeset_eval`, an error of kind `ErrorKind::MissingValue`
+    /// is returned if the base path selects no node.
+    pub fn with_base<'a, X>(&'d self, base_xpath_expr: X) -> Result<Self, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let base_xpath_expr = base_xpath_expr.into();
+        let base = self.with_nodeset_eval(&base_xpath_expr)?;
+        if base.anchor_node().is_none() {
+            return Err(Error::internal(
+                format!("Base path '{}' selects no node.", base_xpath_expr),
+                ErrorKind::MissingValue,
+            )
+            .with_expression(base_xpath_expr.to_string()));
+        }
+        Ok(base)
+    }
+
+    /// Sets the maximum nesting depth of readers derived from this one.
+    ///
+    /// Once a reader would be created more than `max_depth` levels below
+    /// the document root, an error of kind `ErrorKind::DepthLimitExceeded`
+    /// is returned instead. `None` disables the limit, which is the default.
+    ///
+    /// Note that readers created with `from_node` or `from_nodeset` start
+    /// without a limit at depth zero again.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Calls `f` with a reader which has the variable `name` bound to
+    /// `value`, returning the result of `f`.
+    ///
+    /// The reader passed to `f` has the same anchor as this one, but uses a
+    /// copy of this reader's context with the variable added, so neither
+    /// this reader nor a shared `Context` are modified.
+    pub fn with_variable<N, V, F, T>(&'d self, name: N, value: V, f: F) -> T
+    where
+        N: Into<OwnedQName>,
+        V: Into<Value<'d>>,
+        F: FnOnce(&Self) -> T,
+    {
+        let mut context = self.context().clone();
+        context.set_variable(name, value);
+        let reader = Reader {
+            context: Refable::Owned(context),
+            anchor: Anchor::Nodeset(self.anchor_nodeset().into_owned()),
+            depth: self.depth,
+            max_depth: self.max_depth,
+            cache: self.cache.clone(),
+            source: self.source.clone(),
+            xml_version: None,
+        };
+        f(&reader)
+    }
+
+    /// Registers the default namespace of the document under `prefix`, so
+    /// elements in it can be selected with expressions like `//prefix:item`.
+    ///
+    /// Only the `xmlns` declaration of the document's root element is
+    /// considered, default namespaces declared further down are ignored.
+    /// The registered namespace URI is returned, or `None` if the root
+    /// element declares no default namespace.
+    ///
+    /// If this reader was created with a borrowed `Context`, the context is
+    /// copied first and the caller's context stays unmodified.
+    pub fn register_default_namespace(&mut self, prefix: &str) -> Option<String> {
+        let uri = {
+            let document = match self.anchor {
+                Anchor::Root(ref package) => package.as_document(),
+                Anchor::Nodeset(ref nodeset) => nodeset.document_order_first()?.document(),
+            };
+            let root = document
+                .root()
+                .children()
+                .into_iter()
+                .find_map(|c| c.element())?;
+            root.default_namespace_uri()?.to_string()
+        };
+        self.context.to_mut().set_namespace(prefix, &uri);
+        Some(uri)
+    }
+
+    /// Returns the depth of readers for the results of expressions
+    /// evaluated by this reader, checking it against the maximum depth.
+    fn nested_depth(&self) -> Result<usize, Error> {
+        let depth = self.depth + 1;
+        if let Some(max_depth) = self.max_depth {
+            if depth > max_depth {
+                return Err(Error::internal(
+                    format!("Maximum nesting depth of {} exceeded.", max_depth),
+                    ErrorKind::DepthLimitExceeded,
+                ));
+            }
+        }
+        Ok(depth)
+    }
+
+    /// Creates a reader for the result of an expression evaluated by this
+    /// reader, one level deeper than this reader.
+    fn nested(&'d self, nodeset: Nodeset<'d>) -> Result<Self, Error> {
+        let depth = self.nested_depth()?;
+        Ok(self.derived(nodeset, depth))
+    }
+
+    /// Creates a reader for a single node of the result of an expression
+    /// evaluated by this reader, one level deeper than this reader.
+    fn nested_node(&'d self, node: Node<'d>) -> Result<Self, Error> {
+        self.nested(single_nodeset(node))
+    }
+
+    /// Creates a reader anchored at the root of a new document containing
+    /// only the text `value`, see `read` for scalar results.
+    fn scalar_reader(&'d self, value: &str) -> Self {
+        let package = Package::new();
+        {
+            let document = package.as_document();
+            let element = document.create_element("value");
+            element.append_child(document.create_text(value));
+            document.root().append_child(element);
+        }
+        Reader {
+            context: self.context.clone_ref(),
+            anchor: Anchor::Root(Box::new(package)),
+            depth: self.depth,
+            max_depth: self.max_depth,
+            cache: self.cache.clone(),
+            source: None,
+            xml_version: None,
+        }
+    }
+
+    /// Creates a reader for a single node of this reader's anchor nodeset,
+    /// on the same level as this reader.
+    fn node_reader(&'d self, node: Node<'d>) -> Self {
+        self.derived(single_nodeset(node), self.depth)
+    }
+
+    fn derived(&'d self, nodeset: Nodeset<'d>, depth: usize) -> Self {
+        Reader {
+            context: self.context.clone_ref(),
+            anchor: Anchor::Nodeset(nodeset),
+            depth,
+            max_depth: self.max_depth,
+            cache: self.cache.clone(),
+            source: self.source.clone(),
+            xml_version: None,
+        }
+    }
+
+    /// Creates a new `Reader` anchored at the elements selected by `element`
+    /// whose attribute `attr` equals `value`.
+    ///
+    /// This evaluates `element[@attr = value]`, with `value` being quoted
+    /// as an XPath string literal, so it may contain any characters,
+    /// including quotes.
+    pub fn with_nodeset_eval_by_attr(
+        &'d self,
+        element: &str,
+        attr: &str,
+        value: &str,
+    ) -> Result<Self, Error> {
+        let xpath = format!("{}[@{} = {}]", element, attr, escape_xpath_string(value));
+        self.with_nodeset_eval(xpath.as_str())
+    }
+
+    /// Like `with_nodeset_eval` but fails if the result nodeset contains more
+    /// than `max_nodes` nodes.
+    ///
+    /// The limit is checked after the evaluation, so this does not make the
+    /// evaluation itself any cheaper, but guards against processing
+    /// unexpectedly huge results. If the limit is exceeded an error of kind
+    /// `ErrorKind::LimitExceeded` is returned.
+    pub fn with_nodeset_eval_limited<'a, X>(
+        &'d self,
+        xpath_expr: X,
+        max_nodes: usize,
+    ) -> Result<Self, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath = xpath_expr.into();
+        let reader = self.with_nodeset_eval(&xpath)?;
+        let size = reader.anchor_nodeset().size();
+        if size > max_nodes {
+            return Err(Error::internal(
+                format!(
+                    "XPath expression '{}' evaluated to {} nodes, the limit is {}.",
+                    xpath, size, max_nodes
+                ),
+                ErrorKind::LimitExceeded,
+            ));
+        }
+        Ok(reader)
+    }
+
+    /// References the evaluation context of this Reader.
+    pub fn context(&'d self) -> &'d Context<'d> {
+        self.context.borrow()
+    }
+
+    /// Returns the anchor nodeset of the current reader.
+    pub fn anchor_nodeset(&'d self) -> Cow<'d, Nodeset<'d>> {
+        match self.anchor {
+            Anchor::Nodeset(ref nodeset) => Cow::Borrowed(nodeset),
+            Anchor::Root(ref package) => {
+                let mut nodeset = Nodeset::new();
+                let root = package.as_document().root();
+                nodeset.add(Node::Root(root));
+                Cow::Owned(nodeset)
+            }
+        }
+    }
+
+    /// Returns the parsed document, if this reader owns it.
+    ///
+    /// Only readers created by one of the `from_str` or `from_bytes`
+    /// constructors own the document, for all other readers `None` is
+    /// returned. The package can be used with other sxd based code without
+    /// parsing the document again.
+    pub fn package(&self) -> Option<&Package> {
+        match self.anchor {
+            Anchor::Root(ref package) => Some(package),
+            Anchor::Nodeset(_) => None,
+        }
+    }
+
+    /// Consumes this reader and returns the parsed document, if this reader
+    /// owns it.
+    ///
+    /// See `package` for which readers own the document.
+    pub fn into_package(self) -> Option<Package> {
+        match self.anchor {
+            Anchor::Root(package) => Some(*package),
+            Anchor::Nodeset(_) => None,
+        }
+    }
+
+    /// Returns the XML version of the document, like `1.0` or `1.1`.
+    ///
+    /// The version is taken from the XML declaration, if the document has
+    /// none the default version `1.0` is returned. Like for `package`, only
+    /// readers created by one of the `from_str` or `from_bytes` constructors
+    /// know the version, for all other readers `None` is returned.
+    pub fn xml_version(&self) -> Option<&str> {
+        self.xml_version.as_deref()
+    }
+
+    /// Returns the line (counting from one) in the source document on which
+    /// the first node selected by the XPath expression starts.
+    ///
+    /// sxd_document does not track source positions, so this is a best
+    /// effort lookup: The element is found by counting the start tags with
+    /// its name in the source text. For other nodes, like attributes or
+    /// text, the line of the enclosing element's start tag is returned.
+    /// Lines refer to the document as it was parsed, i.e. after expanding
+    /// entities or includes.
+    ///
+    /// The source text is only retained by readers created with
+    /// `from_str_with_source` and the readers derived from them, e.g. by
+    /// `with_nodeset_eval` or for `FromXml` implementations. For all other
+    /// readers, and if no node is selected, `None` is returned.
+    pub fn source_line<'a, X>(&'d self, xpath_expr: X) -> Result<Option<usize>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let source: &str = match self.source {
+            Some(ref source) => source,
+            None => return Ok(None),
+        };
+        let mut element = match self.evaluate_nodeset(xpath_expr)?.document_order_first() {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        while element.element().is_none() {
+            element = match element.parent() {
+                Some(parent) => parent,
+                None => return Ok(None),
+            };
+        }
+
+        let name = element.prefixed_name();
+        let index = self
+            .evaluate_nodeset("//*")?
+            .document_order()
+            .into_iter()
+            .filter(|node| node.prefixed_name() == name)
+            .position(|node| node == element);
+        let line = match (name, index) {
+            (Some(name), Some(index)) => nth_start_tag(source, &name, index)
+                .map(|pos| source[..pos].matches('\n').count() + 1),
+            _ => None,
+        };
+        Ok(line)
+    }
+
+    /// Returns the first (in document order) node in the anchor nodeset.
+    ///
+    /// If the anchor nodeset is empty, `None` will be returned.
+    /// For a reader constructed by `from_node` this is the node passed to it,
+    /// which can be used for interoperation with other sxd based code.
+    pub fn anchor_node(&'d self) -> Option<Node<'d>> {
+        match self.anchor {
+            Anchor::Nodeset(ref nodeset) => nodeset.document_order_first(),
+            Anchor::Root(ref package) => Some(package.as_document().root().into()),
+        }
+    }
+
+    /// Returns whether the anchor node is the document root.
+    ///
+    /// This is the case for readers created from a string, as well as for
+    /// readers anchored at `/`, but not for readers anchored at the root
+    /// element or any other node.
+    pub fn is_root(&'d self) -> bool {
+        matches!(self.anchor_node(), Some(Node::Root(_)))
+    }
+
+    /// Reads the value of the attribute `attr_name` from the anchor node
+    /// or its closest ancestor defining it.
+    ///
+    /// This is useful for inherited attributes like `xml:lang`.
+    /// If neither the anchor node nor any of its ancestors defines the
+    /// attribute, `None` will be returned.
+    pub fn read_inherited_attr(&'d self, attr_name: &str) -> Result<Option<String>, Error> {
+        let xpath = format!("ancestor-or-self::*/@{}", attr_name);
+        let attrs = self.with_nodeset_eval(xpath.as_str())?;
+        let closest = attrs
+            .anchor_nodeset()
+            .document_order()
+            .last()
+            .map(|n| n.string_value());
+        Ok(closest)
+    }
+
+    /// Returns all attributes of the anchor node as a map from attribute
+    /// name to attribute value.
+    ///
+    /// The keys are the local names of the attributes, namespace prefixes
+    /// are dropped. So if several attributes share a local name, like
+    /// `xml:lang` and `lang` or `a:href` and `b:href`, only one of them is
+    /// kept. If the anchor node is not an element the map is empty, if the
+    /// anchor nodeset is empty an error of kind `ErrorKind::MissingValue`
+    /// is returned.
+    pub fn attribute_map(&'d self) -> Result<HashMap<String, String>, Error> {
+        let node = self
+            .anchor_node()
+            .ok_or_else(|| Error::internal("Missing (anchor) node.", ErrorKind::MissingValue))?;
+        Ok(node
+            .element()
+            .map(|e| {
+                e.attributes()
+                    .iter()
+                    .map(|a| (a.name().local_part().to_string(), a.value().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Returns the depth of the anchor node in the document tree.
+    ///
+    /// The depth is the number of ancestors of the node, so the document
+    /// root has depth 0 and the root element has depth 1. If the anchor
+    /// nodeset is empty, an error of kind `ErrorKind::MissingValue` is
+    /// returned.
+    pub fn depth(&'d self) -> Result<usize, Error> {
+        let mut node = self
+            .anchor_node()
+            .ok_or_else(|| Error::internal("Missing (anchor) node.", ErrorKind::MissingValue))?;
+        let mut depth = 0;
+        while let Some(parent) = node.parent() {
+            node = parent;
+            depth += 1;
+        }
+        Ok(depth)
+    }
+
+    /// Returns the qualified name of the anchor node.
+    ///
+    /// If the node's namespace is bound to a prefix in the document the name
+    /// has the form `prefix:local`, otherwise only the local name is
+    /// returned. If the anchor nodeset is empty or the anchor node has no
+    /// name, e.g. for text nodes, an error of kind `ErrorKind::MissingValue`
+    /// is returned.
+    pub fn qualified_name(&'d self) -> Result<String, Error> {
+        self.anchor_node()
+            .ok_or_else(|| Error::internal("Missing (anchor) node.", ErrorKind::MissingValue))?
+            .prefixed_name()
+            .ok_or_else(|| Error::internal("Anchor node has no name.", ErrorKind::MissingValue))
+    }
+
+    /// Returns the local name of the document's root element.
+    ///
+    /// The result does not depend on the anchor of this reader, so it can
+    /// be used on any reader to e.g. distinguish an `<rss>` feed from an
+    /// Atom `<feed>` before reading format-specific fields.
+    pub fn root_element_name(&'d self) -> Result<String, Error> {
+        let name = self.evaluate("local-name(/*)")?.string();
+        if name.is_empty() {
+            return Err(Error::internal(
+                "Document has no root element.",
+                ErrorKind::MissingValue,
+            ));
+        }
+        Ok(name)
+    }
+
+    /// Builds an index of all elements in the document with an `xml:id`
+    /// attribute, mapping each id to a reader anchored at its element.
+    ///
+    /// This allows repeated lookups by id without evaluating an XPath
+    /// expression each time. If an id is used more than once, the first
+    /// element in document order is indexed.
+    pub fn build_id_index(&'d self) -> Result<HashMap<String, Reader<'d>>, Error> {
+        let ids = self.evaluate_nodeset(
+            "//@*[local-name() = 'id' and namespace-uri() = 'http://www.w3.org/XML/1998/namespace']",
+        )?;
+        let mut index = HashMap::new();
+        for id in ids.document_order() {
+            if let Some(element) = id.parent() {
+                if let Entry::Vacant(entry) = index.entry(id.string_value()) {
+                    entry.insert(self.nested_node(element)?);
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Creates a new `Reader` scoped to the first element (in document
+    /// order) with an `id` attribute equal to `id`.
+    ///
+    /// The element is searched in the whole document, regardless of the
+    /// anchor of this reader. If no such element exists, an error of kind
+    /// `ErrorKind::MissingValue` is returned.
+    pub fn scope_to_id(&'d self, id: &str) -> Result<Self, Error> {
+        let element = self
+            .evaluate_nodeset("//*[@id]")?
+            .document_order()
+            .into_iter()
+            .find(|node| node.element().and_then(|e| e.attribute_value("id")) == Some(id))
+            .ok_or_else(|| {
+                Error::internal(
+                    format!("No element with id '{}' found.", id),
+                    ErrorKind::MissingValue,
+                )
+            })?;
+        self.nested_node(element)
+    }
+
+    /// Creates a reader for every descendant element of the anchor node
+    /// with the local name `local_name`, in document order.
+    ///
+    /// In contrast to reading `//name`, only descendants of the anchor node
+    /// are considered, regardless of their depth below it.
+    pub fn descendant_readers(&'d self, local_name: &str) -> Result<Vec<Self>, Error> {
+        let xpath = format!(
+            "descendant::*[local-name() = {}]",
+            escape_xpath_string(local_name)
+        );
+        self.evaluate_nodeset(xpath.as_str())?
+            .document_order()
+            .into_iter()
+            .map(|node| self.nested_node(node))
+            .collect()
+    }
+
+    /// Creates a reader for every child element of the anchor node, paired
+    /// with the element's local name, in document order.
+    ///
+    /// This allows generic parsers to dispatch on the element names in a
+    /// single pass over the children.
+    pub fn child_readers(&'d self) -> Result<Vec<(String, Self)>, Error> {
+        self.evaluate_nodeset("*")?
+            .document_order()
+            .into_iter()
+            .map(|node| {
+                let name = node
+                    .expanded_name()
+                    .map(|n| n.local_part().to_string())
+                    .unwrap_or_default();
+                Ok((name, self.nested_node(node)?))
+            })
+            .collect()
+    }
+
+    /// Creates a new `Reader` anchored at the nearest preceding sibling
+    /// element of the anchor node.
+    ///
+    /// If the anchor node is the first element on its level, `None` is
+    /// returned.
+    pub fn preceding_sibling(&'d self) -> Result<Option<Self>, Error> {
+        let sibling = self.with_nodeset_eval("preceding-sibling::*[1]")?;
+        if sibling.anchor_node().is_some() {
+            Ok(Some(sibling))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the concatenated text of all text nodes following the node
+    /// selected by the XPath expression on the same level.
+    ///
+    /// For `<p>before<b/>after</p>` the following text of `//b` is `after`.
+    /// Note that this includes all following text siblings, not only the
+    /// ones up to the next element. If the expression selects no node, an
+    /// empty string is returned.
+    pub fn following_text<'a, X>(&'d self, xpath_expr: X) -> Result<String, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let node = self.with_nodeset_eval(xpath_expr)?;
+        if node.anchor_node().is_none() {
+            return Ok(String::new());
+        }
+        let texts = node.with_nodeset_eval("following-sibling::text()")?;
+        let text = texts
+            .anchor_nodeset()
+            .document_order()
+            .iter()
+            .map(|n| n.string_value())
+            .collect();
+        Ok(text)
+    }
+
+    /// Returns the content of the first text node which is a direct child
+    /// of the anchor node, or an empty string if there is none.
+    ///
+    /// In contrast to the XPath string value, which concatenates all
+    /// descendant text, this only returns the node's own leading text. For
+    /// `<p>Hello <b>bold</b> world</p>` the string value is
+    /// `Hello bold world`, but this returns `Hello `. See
+    /// `direct_text_nodes` for all direct text children.
+    pub fn node_text(&'d self) -> Result<String, Error> {
+        let text = self.evaluate_nodeset("text()[1]")?;
+        Ok(text
+            .document_order_first()
+            .map(|node| node.string_value())
+            .unwrap_or_default())
+    }
+
+    /// Returns the text nodes which are direct children of the anchor node,
+    /// in document order.
+    ///
+    /// In contrast to the XPath string value, text inside of child elements
+    /// is not included. For `<p>Hello <b>bold</b> world</p>` this returns
+    /// `["Hello ", " world"]`.
+    pub fn direct_text_nodes(&'d self) -> Result<Vec<String>, Error> {
+        self.read("text()")
+    }
+
+    /// Returns the text of all comments which are direct children of the
+    /// node selected by the XPath expression, in document order.
+    ///
+    /// If the expression selects no node, an empty list is returned.
+    pub fn read_comments<'a, X>(&'d self, xpath_expr: X) -> Result<Vec<String>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let node = self.with_nodeset_eval(xpath_expr)?;
+        if node.anchor_node().is_none() {
+            return Ok(Vec::new());
+        }
+        node.read("comment()")
+    }
+
+    /// Returns the target and data of all processing instructions in the
+    /// document, in document order.
+    ///
+    /// For `<?xml-stylesheet href="style.xsl"?>` this would be the pair
+    /// `("xml-stylesheet", "href=\"style.xsl\"")`. A processing instruction
+    /// without data results in an empty data string.
+    pub fn read_processing_instructions(&'d self) -> Result<Vec<(String, String)>, Error> {
+        let pis = self.with_nodeset_eval("//processing-instruction()")?;
+        let pairs = pis
+            .anchor_nodeset()
+            .document_order()
+            .iter()
+            .filter_map(|n| n.processing_instruction())
+            .map(|pi| {
+                (
+                    pi.target().to_string(),
+                    pi.value().unwrap_or("").to_string(),
+                )
+            })
+            .collect();
+        Ok(pairs)
+    }
+
+    /// Reads the XPath expression's result as string and checks that it
+    /// matches `pattern`.
+    ///
+    /// The pattern may match anywhere in the string, use `^` and `$` to
+    /// match the whole value. If it doesn't match an error of kind
+    /// `ErrorKind::FromXml` is returned.
+    #[cfg(feature = "regex")]
+    pub fn read_matching<'a, X>(&'d self, xpath_expr: X, pattern: &Regex) -> Result<String, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        if pattern.is_match(&value) {
+            Ok(value)
+        } else {
+            Err(Error::internal(
+                format!("Value '{}' does not match pattern '{}'.", value, pattern),
+                ErrorKind::FromXml,
+            ))
+        }
+    }
+
+    /// Reads a number formatted with grouping separators, like `1,234.5`
+    /// or `1.234,5`.
+    ///
+    /// All `group_sep` characters are removed and `decimal_sep` is treated
+    /// as decimal point, any other character not valid in a float literal
+    /// results in an error of kind `ErrorKind::FromXml`.
+    pub fn read_grouped_number<'a, X>(
+        &'d self,
+        xpath_expr: X,
+        group_sep: char,
+        decimal_sep: char,
+    ) -> Result<f64, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        let invalid = || {
+            Error::internal(
+                format!("Invalid grouped number: '{}'", value),
+                ErrorKind::FromXml,
+            )
+        };
+        let mut normalized = String::with_capacity(value.len());
+        for c in value.trim().chars() {
+            if c == group_sep {
+                continue;
+            } else if c == decimal_sep {
+                normalized.push('.');
+            } else if c == '.' {
+                return Err(invalid());
+            } else {
+                normalized.push(c);
+            }
+        }
+        normalized.parse().map_err(|_| invalid())
+    }
+
+    /// Reads a whitespace separated list of bytes, like `255 0 128`.
+    ///
+    /// Each token is parsed with the given `radix` (e.g. `10` or `16`),
+    /// tokens which are invalid or out of the range of `u8` result in an
+    /// error of kind `ErrorKind::FromXml`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `radix` is not in the range from 2 to 36.
+    pub fn read_byte_list<'a, X>(&'d self, xpath_expr: X, radix: u32) -> Result<Vec<u8>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        value
+            .split_whitespace()
+            .map(|token| {
+                u8::from_str_radix(token, radix).map_err(|e| {
+                    Error::internal(
+                        format!("Invalid byte '{}': {}", token, e),
+                        ErrorKind::FromXml,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Reads a percentage like `73%` or `73`.
+    ///
+    /// An optional trailing `%` is stripped and the remaining number is
+    /// returned as is, i.e. `73%` results in `73.0` rather than `0.73`.
+    /// Values outside of `0..=100` are rejected, as are values which are
+    /// not a number, both with an error of kind `ErrorKind::FromXml`.
+    pub fn read_percentage<'a, X>(&'d self, xpath_expr: X) -> Result<f64, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        let trimmed = value.trim();
+        let number = trimmed.strip_suffix('%').unwrap_or(trimmed).trim_end();
+        let percentage: f64 = number.parse().map_err(|_| {
+            Error::internal(
+                format!("Invalid percentage: '{}'", value),
+                ErrorKind::FromXml,
+            )
+        })?;
+        if !(0.0..=100.0).contains(&percentage) {
+            return Err(Error::internal(
+                format!("Percentage out of range 0..=100: '{}'", value),
+                ErrorKind::FromXml,
+            ));
+        }
+        Ok(percentage)
+    }
+
+    /// Reads an XML Schema boolean.
+    ///
+    /// In contrast to reading a `bool`, which only accepts `true` and
+    /// `false`, this also accepts `1` and `0` as defined for `xsd:boolean`.
+    /// Leading and trailing whitespace is ignored, any other value results
+    /// in an error of kind `ErrorKind::FromXml`.
+    pub fn read_xsd_bool<'a, X>(&'d self, xpath_expr: X) -> Result<bool, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        match value.trim() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(Error::internal(
+                format!("Invalid xsd:boolean: '{}'", value),
+                ErrorKind::FromXml,
+            )),
+        }
+    }
+
+    /// Reads a required number, distinguishing a missing value from an
+    /// invalid one.
+    ///
+    /// If no node is selected or its value is empty after trimming, an error
+    /// of kind `ErrorKind::MissingValue` is returned. Otherwise the trimmed
+    /// value is parsed, failing with an error of kind `ErrorKind::FromXml`.
+    pub fn read_required_number<'a, T, X>(&'d self, xpath_expr: X) -> Result<T, Error>
+    where
+        T: str::FromStr,
+        T::Err: fmt::Display,
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath_expr = xpath_expr.into();
+        let value: Option<String> = self.read(&xpath_expr)?;
+        let value = value.as_deref().map(str::trim).unwrap_or("");
+        if value.is_empty() {
+            return Err(Error::internal(
+                format!("Missing required number: '{}'", xpath_expr),
+                ErrorKind::MissingValue,
+            ));
+        }
+        value.parse().map_err(|e| {
+            Error::internal(
+                format!("Invalid number '{}': {}", value, e),
+                ErrorKind::FromXml,
+            )
+        })
+    }
+
+    /// Reads an ISO-8601 duration like `PT1H30M` into a `Duration`.
+    ///
+    /// Days, hours, minutes and (possibly fractional) seconds are supported.
+    /// Years, months and weeks have no fixed length and result in an error
+    /// of kind `ErrorKind::FromXml`, as does any malformed duration.
+    pub fn read_iso_duration<'a, X>(&'d self, xpath_expr: X) -> Result<Duration, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        parse_iso_duration(value.trim())
+            .map_err(|msg| Error::internal(format!("{}: '{}'", msg, value), ErrorKind::FromXml))
+    }
+
+    /// Reads a list of values separated by `sep`, like `admin, editor`,
+    /// parsing each token with `FromStr`.
+    ///
+    /// Tokens are trimmed and empty tokens are skipped. If any token fails
+    /// to parse, an error of kind `ErrorKind::FromXml` listing all
+    /// offending tokens is returned.
+    pub fn read_enum_list<'a, T, X>(&'d self, xpath_expr: X, sep: &str) -> Result<Vec<T>, Error>
+    where
+        T: str::FromStr,
+        T::Err: fmt::Display,
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        let mut values = Vec::new();
+        let mut invalid = Vec::new();
+        for token in value.split(sep).map(str::trim).filter(|t| !t.is_empty()) {
+            match token.parse() {
+                Ok(v) => values.push(v),
+                Err(e) => invalid.push(format!("'{}' ({})", token, e)),
+            }
+        }
+        if !invalid.is_empty() {
+            return Err(Error::internal(
+                format!("Invalid list items: {}", invalid.join(", ")),
+                ErrorKind::FromXml,
+            ));
+        }
+        Ok(values)
+    }
+
+    /// Returns the number of distinct string values of the nodes selected by
+    /// the XPath expression.
+    pub fn count_distinct<'a, X>(&'d self, xpath_expr: X) -> Result<usize, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let nodes = self.evaluate_nodeset(xpath_expr)?;
+        let values: HashSet<String> = nodes.iter().map(|n| n.string_value()).collect();
+        Ok(values.len())
+    }
+
+    /// Returns the distinct values of the attribute `name` of the elements
+    /// selected by the XPath expression, in the order they are first seen.
+    ///
+    /// Elements without the attribute are skipped.
+    pub fn distinct_attr_values<'a, X>(
+        &'d self,
+        xpath_expr: X,
+        name: &str,
+    ) -> Result<Vec<String>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let elements = self.evaluate_nodeset(xpath_expr)?;
+        let mut seen = HashSet::new();
+        let values = elements
+            .document_order()
+            .into_iter()
+            .filter_map(|node| node.element()?.attribute_value(name))
+            .filter(|value| seen.insert(*value))
+            .map(str::to_string)
+            .collect();
+        Ok(values)
+    }
+
+    /// Returns the sum of the numeric values of the nodes selected by the
+    /// XPath expression, or `0` if no node is selected.
+    ///
+    /// Values which are not a number, including `NaN`, result in an error
+    /// of kind `ErrorKind::FromXml` instead of being ignored.
+    pub fn sum<'a, X>(&'d self, xpath_expr: X) -> Result<f64, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        Ok(self.numbers(xpath_expr)?.into_iter().sum())
+    }
+
+    /// Returns the minimum of the numeric values of the nodes selected by
+    /// the XPath expression, or `None` if no node is selected.
+    ///
+    /// Invalid values are handled like for `sum`.
+    pub fn min<'a, X>(&'d self, xpath_expr: X) -> Result<Option<f64>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        Ok(self.numbers(xpath_expr)?.into_iter().reduce(f64::min))
+    }
+
+    /// Returns the maximum of the numeric values of the nodes selected by
+    /// the XPath expression, or `None` if no node is selected.
+    ///
+    /// Invalid values are handled like for `sum`.
+    pub fn max<'a, X>(&'d self, xpath_expr: X) -> Result<Option<f64>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        Ok(self.numbers(xpath_expr)?.into_iter().reduce(f64::max))
+    }
+
+    fn numbers<'a, X>(&'d self, xpath_expr: X) -> Result<Vec<f64>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        self.evaluate_nodeset(xpath_expr)?
+            .document_order()
+            .into_iter()
+            .map(|node| {
+                let value = node.string_value();
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| !n.is_nan())
+                    .ok_or_else(|| {
+                        Error::internal(format!("Invalid number: '{}'", value), ErrorKind::FromXml)
+                    })
+            })
+            .collect()
+    }
+
+    /// Reads the key `key_xpath_expr` of each node selected by
+    /// `node_xpath_expr`, asserting that all keys are unique.
+    ///
+    /// The keys are returned in document order of their nodes. If a key
+    /// occurs more than once, an error of kind `ErrorKind::FromXml` naming
+    /// the duplicated key is returned.
+    pub fn read_unique_keys<'a, 'b, X, Y>(
+        &'d self,
+        node_xpath_expr: X,
+        key_xpath_expr: Y,
+    ) -> Result<Vec<String>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+        Y: Into<XPathExpression<'b>>,
+    {
+        let nodes = self.with_nodeset_eval(node_xpath_expr)?;
+        let key_xpath_expr = key_xpath_expr.into();
+        let key_xpath = key_xpath_expr.parsed()?;
+        let key_xpath: &XPath = key_xpath.borrow();
+
+        let mut seen = HashSet::new();
+        let mut keys = Vec::new();
+        for node in nodes.anchor_nodeset().document_order() {
+            let key: String = nodes.node_reader(node).read(key_xpath)?;
+            if !seen.insert(key.clone()) {
+                return Err(Error::internal(
+                    format!("Duplicate key: '{}'", key),
+                    ErrorKind::FromXml,
+                ));
+            }
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    /// Reads an IPv4 network in CIDR notation, like `10.0.0.0/8`, into the
+    /// address and the prefix length.
+    ///
+    /// The prefix length has to be in the range `0..=32`, otherwise or if
+    /// the value is malformed an error of kind `ErrorKind::FromXml` is
+    /// returned.
+    pub fn read_cidr<'a, X>(&'d self, xpath_expr: X) -> Result<(Ipv4Addr, u8), Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let value: String = self.read(xpath_expr)?;
+        let invalid = || {
+            Error::internal(
+                format!("Invalid CIDR notation: '{}'", value),
+                ErrorKind::FromXml,
+            )
+        };
+        let mut parts = value.trim().splitn(2, '/');
+        let addr = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+        let prefix = parts
+            .next()
+            .and_then(|p| p.parse::<u8>().ok())
+            .filter(|&p| p <= 32)
+            .ok_or_else(invalid)?;
+        Ok((addr, prefix))
+    }
+
+    /// Evaluates the XPath expression as a number and converts it to `usize`.
+    ///
+    /// XPath numbers are always floating point numbers, even for results of
+    /// functions like `count()`. Instead of silently rounding, this fails
+    /// with an error of kind `ErrorKind::FromXml` if the number is negative,
+    /// NaN, has a fractional part or is too large for `usize`.
+    pub fn evaluate_usize<'a, X>(&'d self, xpath_expr: X) -> Result<usize, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let number = self.evaluate(xpath_expr)?.number();
+        if number >= 0. && number.fract() == 0. && number < usize::MAX as f64 {
+            Ok(number as usize)
+        } else {
+            Err(Error::internal(
+                format!("Number {} can't be converted to usize.", number),
+                ErrorKind::FromXml,
+            ))
+        }
+    }
+
+    /// Reads the string value of the XPath expression converted to lowercase.
+    ///
+    /// Like XPath's `translate()` the conversion only covers ASCII letters,
+    /// other characters are left unchanged. If the expression selects no
+    /// node, an empty string is returned.
+    pub fn read_lower<'a, X>(&'d self, xpath_expr: X) -> Result<String, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        Ok(self.evaluate(xpath_expr)?.string().to_ascii_lowercase())
+    }
+
+    /// Reads the string value of the XPath expression converted to uppercase.
+    ///
+    /// Like XPath's `translate()` the conversion only covers ASCII letters,
+    /// other characters are left unchanged. If the expression selects no
+    /// node, an empty string is returned.
+    pub fn read_upper<'a, X>(&'d self, xpath_expr: X) -> Result<String, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        Ok(self.evaluate(xpath_expr)?.string().to_ascii_uppercase())
+    }
+
+    /// Creates a new `Reader` anchored at all descendant elements of the
+    /// anchor node whose local name matches `local_name`, ignoring case.
+    ///
+    /// This is useful for HTML-like documents with inconsistent tag casing.
+    /// Like `read_lower` only ASCII letters are case folded.
+    pub fn read_ci(&'d self, local_name: &str) -> Result<Self, Error> {
+        let xpath = format!(
+            ".//*[translate(local-name(), '{}', '{}') = {}]",
+            ASCII_UPPERCASE,
+            ASCII_LOWERCASE,
+            escape_xpath_string(&local_name.to_ascii_lowercase())
+        );
+        self.with_nodeset_eval(xpath.as_str())
+    }
+
+    fn evaluate_nodeset<'a, X>(&'d self, xpath_expr: X) -> Result<Nodeset<'d>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath = xpath_expr.into();
+        match self.evaluate(&xpath)? {
+            Value::Nodeset(nodeset) => Ok(nodeset),
+            _ => Err(Error::internal(
+                format!("XPath expression did not evaluate to nodeset: '{}'", xpath),
+                ErrorKind::EvalXPath,
+            )),
+        }
+    }
+
+    fn evaluate<'a, X>(&'d self, xpath_expr: X) -> Result<Value<'d>, Error>
+    where
+        X: Into<XPathExpression<'a>>,
+    {
+        let xpath_expr = xpath_expr.into();
+        let value = match xpath_expr.unparsed() {
+            Some(s) => {
+                let xpath = self.cached_xpath(s)?;
+                self.evaluate_xpath(&xpath)
+            }
+            None => {
+                let xpath = xpath_expr.parsed()?;
+                self.evaluate_xpath(xpath.borrow())
+            }
+        };
+        value.map_err(|e| e.with_expression(xpath_expr.to_string()))
+    }
+
+    fn cached_xpath(&self, xpath_expr: &str) -> Result<Rc<XPath>, Error> {
+        if let Some(xpath) = RefCell::borrow(&self.cache).get(xpath_expr) {
+            return Ok(xpath.clone());
+        }
+        let xpath = Rc::new(parse_xpath(xpath_expr)?);
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= MAX_CACHED_EXPRESSIONS {
+            cache.clear();
+        }
+        cache.insert(xpath_expr.to_string(), xpath.clone());
+        Ok(xpath)
+    }
+
+    fn evaluate_xpath(&'d self, xpath: &XPath) -> Result<Value<'d>, Error> {
+        // TODO: Error message.
+        let anchor = self.anchor_node().ok_or_else(|| {
+            Error::internal(
+                format!("Anchor node not found when evaluating: {:?}", xpath),
+                ErrorKind::EvalXPath,
+            )
+        })?;
+
+        xpath
+            .evaluate(self.context().inner(), anchor)
+            .map_err(|e| Error::internal(format!("{}", e), ErrorKind::EvalXPath))
+    }
+}
+
+/// Reads the XPath string value of the anchor node.
+///
+/// CDATA sections are transparent, i.e. `<code><![CDATA[a < b]]></code>`
+/// is read as `a < b` just like `<code>a &lt; b</code>`.
+///
+/// References are decoded by the XML parser, this covers the predefined
+/// entities (`&lt;`, `&gt;`, `&amp;`, `&apos;`, `&quot;`) and decimal
+/// (`&#65;`) as well as hexadecimal (`&#x41;`) character references, in
+/// element content and attribute values alike. Other entities have to be
+/// expanded with `Reader::from_str_with_entities`.
+impl FromXml for String {
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
+        reader
+            .anchor_node()
+            .ok_or_else(|| Error::custom_msg("Missing (anchor) node."))
+            .map(|n| n.string_value())
+    }
+}
+
+impl FromXml for Option<String> {
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
+        Ok(reader.anchor_node().and_then(|node| {
+            let s = node.string_value();
+            if s.is_empty() {
+                None
+            } else {
+                Some(s)
+            }
+        }))
+    }
+}
+
+impl<T> FromXml for Vec<T>
+where
+    T: FromXml,
+{
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
+        reader
+            .anchor_nodeset()
+            .document_order()
+            .iter()
+            .map(|node| {
+                let reader = reader.node_reader(*node);
+                T::from_xml(&reader)
+            })
+            .collect()
+    }
+}
+
+impl<T> FromXml for VecDeque<T>
+where
+    T: FromXml,
+{
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
+        Vec::<T>::from_xml(reader).map(VecDeque::from)
+    }
+}
+
+/// Reads the nodes into a sorted set, dropping duplicate values.
+impl<T> FromXml for BTreeSet<T>
+where
+    T: FromXml + Ord,
+{
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error> {
+        Vec::<T>::from_xml(reader).map(|values| values.into_iter().collect())
+    }
+}
+
+// Numbers are parsed with Rust's `str::parse` rather than XPath's `number()`,
+// so for floats `1e10`, `inf` and `NaN` are accepted while anything else
+// which isn't a valid float literal results in an error.
+macro_rules! from_parse_str {
+    ( $( $type:ty ),* ) => {
+        $(
+            impl FromXml for $type {
+                fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error>
+                {
+                    let s = String::from_xml(reader)?;
+                    s.parse::<$type>().map_err(|e| Error::internal(e, ErrorKind::FromXml))
+                }
+            }
+
+            impl FromXml for Option<$type> {
+                fn from_xml<'d>(reader: &'d Reader<'d>) -> Result<Self, Error>
+                {
+                    if let Some(s) = Option::<String>::from_xml(reader)? {
+                        Ok(Some(s.parse::<$type>().map_err(|e| Error::internal(e, ErrorKind::FromXml))?))
                     } else {
                         Ok(None)
                     }
                 }
             }
-        )*
+        )*
+    }
+}
+
+from_parse_str!(f32, f64, u8, u16, u32, u64, i8, i16, i32, i64, bool, Ipv4Addr);
+
+/// Reads an exact decimal number, ignoring surrounding whitespace.
+#[cfg(feature = "rust_decimal")]
+impl FromXmlOptional for Decimal {
+    fn from_xml_optional<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Option<Self>> {
+        match Option::<String>::from_xml(reader)? {
+            Some(ref s) if !s.trim().is_empty() => s
+                .trim()
+                .parse::<Decimal>()
+                .map(Some)
+                .map_err(|e| Error::internal(e, ErrorKind::FromXml)),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xpath_str_reader() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+                     <root><child name="Hello World"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(
+            reader.evaluate(".//child/@name").unwrap().string(),
+            "Hello World".to_string()
+        );
+    }
+
+    #[test]
+    fn reader_with_includes() {
+        let xml = r#"<?xml version="1.0"?>
+                     <book xmlns:xi="http://www.w3.org/2001/XInclude">
+                     <xi:include href="ch1.xml"/><xi:include href='ch2.xml'></xi:include></book>"#;
+        let resolver = |href: &str| match href {
+            "ch1.xml" => Ok(r#"<?xml version="1.0"?><chapter>One</chapter>"#.to_string()),
+            "ch2.xml" => Ok("<chapter>Two</chapter>".to_string()),
+            "loop.xml" => Ok(
+                r#"<x:include xmlns:x="http://www.w3.org/2001/XInclude" href="loop.xml"/>"#
+                    .to_string(),
+            ),
+            _ => Err(Error::custom_msg("Not found.")),
+        };
+
+        let reader = Reader::from_str_with_includes(xml, resolver, None).unwrap();
+        let chapters: Vec<String> = reader.read("//chapter").unwrap();
+        assert_eq!(chapters, vec!["One".to_string(), "Two".to_string()]);
+
+        let xml = r#"<?xml version="1.0"?>
+                     <book xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="loop.xml"/></book>"#;
+        let err = Reader::from_str_with_includes(xml, resolver, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+    }
+
+    #[test]
+    fn reader_with_includes_skips_comments() {
+        let xml = r#"<book xmlns:xi="http://www.w3.org/2001/XInclude">
+                     <!-- <xi:include href="missing.xml"/> -->
+                     <![CDATA[<xi:include href="missing.xml"/>]]>
+                     <xi:include href="ch1.xml"/></book>"#;
+        let resolver = |href: &str| match href {
+            "ch1.xml" => Ok("<chapter>One</chapter>".to_string()),
+            _ => Err(Error::custom_msg("Not found.")),
+        };
+
+        let reader = Reader::from_str_with_includes(xml, resolver, None).unwrap();
+        let chapters: Vec<String> = reader.read("//chapter").unwrap();
+        assert_eq!(chapters, vec!["One".to_string()]);
+        let comment: String = reader.read("//comment()").unwrap();
+        assert_eq!(comment, r#" <xi:include href="missing.xml"/> "#);
+    }
+
+    #[test]
+    fn reader_with_includes_default_namespace() {
+        let xml =
+            r#"<book><include xmlns="http://www.w3.org/2001/XInclude" href="ch1.xml"/></book>"#;
+        let resolver = |_: &str| Ok("<chapter>One</chapter>".to_string());
+
+        let reader = Reader::from_str_with_includes(xml, resolver, None).unwrap();
+        let chapters: Vec<String> = reader.read("//chapter").unwrap();
+        assert!(chapters.is_empty());
+        let href: String = reader.read("/book/*/@href").unwrap();
+        assert_eq!(href, "ch1.xml");
+    }
+
+    #[test]
+    fn well_formed() {
+        assert!(is_well_formed(r#"<?xml version="1.0"?><root><a/></root>"#).is_ok());
+
+        let err = is_well_formed(r#"<?xml version="1.0"?><root><a></root>"#)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+    }
+
+    #[test]
+    fn empty_document() {
+        for xml in &[
+            "",
+            "  \n\t ",
+            r#"<?xml version="1.0"?>"#,
+            "<?xml version=\"1.0\"?>\n",
+            "\u{feff}",
+            "\u{feff}<?xml version=\"1.0\"?>\n",
+            "<?xml version=\"1.0\"?>\n<!-- no content -->\n",
+            "<?xml version=\"1.0\"?><?xml-stylesheet href=\"a.xsl\"?><!-- c -->",
+        ] {
+            let err = Reader::from_str(xml, None).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::MissingValue);
+            assert!(err.to_string().contains("Empty XML document"));
+        }
+        let err = Reader::from_bytes(b"", None).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+
+        let reader = Reader::from_str("<r/>", None).unwrap();
+        assert_eq!(reader.root_element_name().unwrap(), "r");
+        let err = Reader::from_str(" <?xml", None).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+        let err = Reader::from_str("<!-- unterminated", None).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+    }
+
+    #[test]
+    fn reader_from_bytes() {
+        let xml = "<?xml version=\"1.0\"?><root><title>Grüezi</title></root>".as_bytes();
+        let reader = Reader::from_bytes(xml, None).unwrap();
+        let title: String = reader.read("//title").unwrap();
+        assert_eq!(title, "Grüezi");
+
+        let invalid = b"<?xml version=\"1.0\"?><root>\xff\xfe</root>";
+        let err = Reader::from_bytes(invalid, None).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+    }
+
+    #[test]
+    fn reader_with_entities() {
+        let xml = r#"<?xml version="1.0"?><root><p>&custom; &amp; &#65;</p></root>"#;
+        let mut entities = HashMap::new();
+        entities.insert("custom".to_string(), "Hello World".to_string());
+
+        let reader = Reader::from_str_with_entities(xml, &entities, None).unwrap();
+        let p: String = reader.read("//p").unwrap();
+        assert_eq!(p, "Hello World & A");
+
+        let xml = r#"<?xml version="1.0"?><root><p>&undefined;</p></root>"#;
+        let err = Reader::from_str_with_entities(xml, &entities, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ParseXml);
+
+        let xml = r#"<?xml version="1.0"?>
+                     <root><!-- &x; --><p><![CDATA[&x; &undefined;]]> &x;</p><?pi &undefined;?></root>"#;
+        entities.insert("x".to_string(), "X".to_string());
+        let reader = Reader::from_str_with_entities(xml, &entities, None).unwrap();
+        let p: String = reader.read("//p").unwrap();
+        assert_eq!(p, "&x; &undefined; X");
+        let comment: String = reader.read("//comment()").unwrap();
+        assert_eq!(comment, " &x; ");
+    }
+
+    #[test]
+    fn anchor_node_from_node() {
+        let xml = r#"<?xml version="1.0"?><root><a/><b/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let b = reader.with_nodeset_eval("//b").unwrap();
+        let node = b.anchor_node().unwrap();
+
+        let node_reader = Reader::from_node(node, None);
+        assert_eq!(node_reader.anchor_node(), Some(node));
+    }
+
+    #[test]
+    fn package() {
+        let xml = r#"<?xml version="1.0"?><root><a>1</a></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.read::<u32, _>("//a").unwrap(), 1);
+        assert!(reader.with_nodeset_eval("//a").unwrap().package().is_none());
+
+        let root_name = |package: &Package| {
+            let children = package.as_document().root().children();
+            children[0]
+                .element()
+                .unwrap()
+                .name()
+                .local_part()
+                .to_string()
+        };
+        assert_eq!(root_name(reader.package().unwrap()), "root");
+        let package = reader.into_package().unwrap();
+        assert_eq!(root_name(&package), "root");
+    }
+
+    #[test]
+    fn xml_version() {
+        let xml = r#"<?xml version="1.1" encoding="UTF-8"?><root><a/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.xml_version(), Some("1.1"));
+        assert_eq!(reader.with_nodeset_eval("//a").unwrap().xml_version(), None);
+
+        let reader = Reader::from_str("<root/>", None).unwrap();
+        assert_eq!(reader.xml_version(), Some("1.0"));
+        let xml = "<?xml version = '1.0'?><root/>";
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.xml_version(), Some("1.0"));
+    }
+
+    #[test]
+    fn source_line() {
+        let xml = r#"<?xml version="1.0"?>
+<root>
+  <!-- <item>commented</item> -->
+  <item id="1">a</item>
+  <items><![CDATA[<item>]]></items>
+  <item
+    id="2">b</item>
+</root>"#;
+        let reader = Reader::from_str_with_source(xml, None).unwrap();
+
+        assert_eq!(reader.source_line("/root").unwrap(), Some(2));
+        assert_eq!(reader.source_line("//item[1]").unwrap(), Some(4));
+        assert_eq!(reader.source_line("//item[2]").unwrap(), Some(6));
+        assert_eq!(reader.source_line("//item[@id = 2]/@id").unwrap(), Some(6));
+        assert_eq!(reader.source_line("//item[2]/text()").unwrap(), Some(6));
+        assert_eq!(reader.source_line("//absent").unwrap(), None);
+        let root = reader.with_nodeset_eval("/root").unwrap();
+        assert_eq!(root.source_line("item[2]").unwrap(), Some(6));
+        let lines: Vec<Option<usize>> = root
+            .nodes("item")
+            .unwrap()
+            .map(|item| item.source_line(".").unwrap())
+            .collect();
+        assert_eq!(lines, vec![Some(4), Some(6)]);
+
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.source_line("/root").unwrap(), None);
+        let node = reader.with_nodeset_eval("//item").unwrap();
+        let node = Reader::from_node(node.anchor_node().unwrap(), None);
+        assert_eq!(node.source_line(".").unwrap(), None);
+    }
+
+    #[test]
+    fn is_root() {
+        let xml = r#"<?xml version="1.0"?><root><a/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert!(reader.is_root());
+        assert!(reader.with_nodeset_eval("/").unwrap().is_root());
+        assert!(!reader.with_nodeset_eval("/root").unwrap().is_root());
+        assert!(!reader.with_nodeset_eval("//a").unwrap().is_root());
+        assert!(!reader.with_nodeset_eval("//absent").unwrap().is_root());
+    }
+
+    #[test]
+    fn debug() {
+        let xml = r#"<?xml version="1.0"?><root><title>Hello</title><title/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let title = reader.with_nodeset_eval("//title").unwrap();
+
+        let debug = format!("{:?}", reader);
+        assert!(debug.contains(r#"anchor_node: Some("/")"#));
+        let debug = format!("{:?}", title);
+        assert!(debug.contains("anchor_size: 2"));
+        assert!(debug.contains(r#"anchor_node: Some("title")"#));
+        assert!(debug.contains("depth: 1"));
+    }
+
+    #[test]
+    fn inherited_attr() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root lang="de"><section lang="en"><p><b/></p></section><p/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let b = reader.with_nodeset_eval("//b").unwrap();
+        assert_eq!(
+            b.read_inherited_attr("lang").unwrap(),
+            Some("en".to_string())
+        );
+        let p = reader.with_nodeset_eval("/root/p").unwrap();
+        assert_eq!(
+            p.read_inherited_attr("lang").unwrap(),
+            Some("de".to_string())
+        );
+        assert_eq!(p.read_inherited_attr("dir").unwrap(), None);
+    }
+
+    #[test]
+    fn attribute_map() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root xmlns:l="links"><a href="/home" title="Home" l:rel="start"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let a = reader.with_nodeset_eval("//a").unwrap();
+        let attrs = a.attribute_map().unwrap();
+        assert_eq!(attrs.len(), 3);
+        assert_eq!(attrs["href"], "/home");
+        assert_eq!(attrs["title"], "Home");
+        assert_eq!(attrs["rel"], "start");
+
+        let missing = reader.with_nodeset_eval("//missing").unwrap();
+        let err = missing.attribute_map().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn with_base() {
+        let xml = r#"<?xml version="1.0"?>
+                     <library><book><title>Neuromancer</title><author>William Gibson</author>
+                     <year>1984</year></book></library>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let book = reader.with_base("/library/book[1]").unwrap();
+        assert_eq!(book.read::<String, _>("title").unwrap(), "Neuromancer");
+        assert_eq!(book.read::<String, _>("author").unwrap(), "William Gibson");
+        assert_eq!(book.read::<u32, _>("year").unwrap(), 1984);
+
+        let err = reader.with_base("/library/magazine").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        assert_eq!(err.expression(), Some("/library/magazine"));
+    }
+
+    #[test]
+    fn nodeset_eval_by_attr() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><book title="Neuromancer">1</book><book title="Ender's Game">2</book>
+                     <book title='Say "Hi" &amp; "Bye"'>3</book><book title="It's &quot;odd&quot;">4</book></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let read = |title| {
+            let book = reader
+                .with_nodeset_eval_by_attr("//book", "title", title)
+                .unwrap();
+            Option::<u32>::from_xml(&book).unwrap()
+        };
+        assert_eq!(read("Neuromancer"), Some(1));
+        assert_eq!(read("Ender's Game"), Some(2));
+        assert_eq!(read(r#"Say "Hi" & "Bye""#), Some(3));
+        assert_eq!(read(r#"It's "odd""#), Some(4));
+        assert_eq!(read("' or '1' = '1"), None);
+    }
+
+    #[test]
+    fn nodeset_eval_limited() {
+        let xml = r#"<?xml version="1.0"?><root><a/><a/><a/><a/><a/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let all = reader.with_nodeset_eval_limited("//a", 5).unwrap();
+        assert_eq!(all.anchor_nodeset().size(), 5);
+
+        let err = reader.with_nodeset_eval_limited("//a", 2).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    }
+
+    #[test]
+    fn depth() {
+        let xml = r#"<?xml version="1.0"?><root><a><b c="d"/></a></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.depth().unwrap(), 0);
+        assert_eq!(
+            reader.with_nodeset_eval("/root").unwrap().depth().unwrap(),
+            1
+        );
+        assert_eq!(reader.with_nodeset_eval("//b").unwrap().depth().unwrap(), 3);
+        assert_eq!(
+            reader.with_nodeset_eval("//@c").unwrap().depth().unwrap(),
+            4
+        );
+        let missing = reader.with_nodeset_eval("//missing").unwrap();
+        assert_eq!(
+            missing.depth().err().unwrap().kind(),
+            ErrorKind::MissingValue
+        );
+    }
+
+    #[test]
+    fn qualified_name() {
+        let xml = r#"<?xml version="1.0"?>
+                     <feed xmlns:atom="http://www.w3.org/2005/Atom"><atom:entry/><entry/></feed>"#;
+        let mut context = Context::new();
+        context.set_namespace("a", "http://www.w3.org/2005/Atom");
+        let reader = Reader::from_str(xml, Some(&context)).unwrap();
+
+        let prefixed = reader.with_nodeset_eval("//a:entry").unwrap();
+        assert_eq!(prefixed.qualified_name().unwrap(), "atom:entry");
+        let plain = reader.with_nodeset_eval("/feed/entry").unwrap();
+        assert_eq!(plain.qualified_name().unwrap(), "entry");
+        let err = reader.qualified_name().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        let missing = reader.with_nodeset_eval("//missing").unwrap();
+        let err = missing.qualified_name().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn root_element_name() {
+        let xml = r#"<?xml version="1.0"?><rss version="2.0"><channel/></rss>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.root_element_name().unwrap(), "rss");
+        let channel = reader.with_nodeset_eval("//channel").unwrap();
+        assert_eq!(channel.root_element_name().unwrap(), "rss");
+
+        let xml =
+            r#"<?xml version="1.0"?><feed xmlns="http://www.w3.org/2005/Atom"><entry/></feed>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.root_element_name().unwrap(), "feed");
+    }
+
+    #[test]
+    fn id_index() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><sec xml:id="intro"><title>Introduction</title></sec>
+                     <sec xml:id="end"><title>Conclusion</title></sec><sec id="other"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let index = reader.build_id_index().unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index["end"].read::<String, _>("title").unwrap(),
+            "Conclusion"
+        );
+        assert_eq!(
+            index["intro"].read::<String, _>("title").unwrap(),
+            "Introduction"
+        );
+    }
+
+    #[test]
+    fn scope_to_id() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><sec id="intro"><title>Introduction</title></sec>
+                     <sec id="it's"><title>Quoted</title></sec></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let intro = reader.scope_to_id("intro").unwrap();
+        assert_eq!(intro.read::<String, _>("title").unwrap(), "Introduction");
+        let quoted = intro.scope_to_id("it's").unwrap();
+        assert_eq!(quoted.read::<String, _>("title").unwrap(), "Quoted");
+        let err = reader.scope_to_id("missing").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn descendant_readers() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><node id="outside"/>
+                     <tree><node id="1"><node id="2"><node id="3"/></node></node><leaf/><node id="4"/></tree></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let tree = reader.with_nodeset_eval("//tree").unwrap();
+
+        let nodes = tree.descendant_readers("node").unwrap();
+        let ids: Vec<String> = nodes.iter().map(|n| n.read("@id").unwrap()).collect();
+        assert_eq!(ids, vec!["1", "2", "3", "4"]);
+        assert!(tree.descendant_readers("absent").unwrap().is_empty());
+    }
+
+    #[test]
+    fn child_readers() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root xmlns:x="urn:x"><person><name>Ada</name>text<x:age>36</x:age>
+                     <!-- c --><name>Lovelace</name><email><name>nested</name></email></person></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let person = reader.with_nodeset_eval("//person").unwrap();
+
+        let children = person.child_readers().unwrap();
+        let names: Vec<&str> = children.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(names, vec!["name", "age", "name", "email"]);
+        assert_eq!(children[0].1.read::<String, _>(".").unwrap(), "Ada");
+        assert_eq!(children[1].1.read::<u32, _>(".").unwrap(), 36);
+        assert_eq!(children[2].1.read::<String, _>(".").unwrap(), "Lovelace");
+        assert!(children[2].1.child_readers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn preceding_sibling() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><h1>Title</h1>text<p>First</p><!-- c --><p>Second</p></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let second = reader.with_nodeset_eval("//p[2]").unwrap();
+        let first = second.preceding_sibling().unwrap().unwrap();
+        assert_eq!(first.read::<String, _>(".").unwrap(), "First");
+        let h1 = first.preceding_sibling().unwrap().unwrap();
+        assert_eq!(h1.read::<String, _>(".").unwrap(), "Title");
+        assert!(h1.preceding_sibling().unwrap().is_none());
+    }
+
+    #[test]
+    fn following_text() {
+        let xml = r#"<?xml version="1.0"?><root><p>before<b>bold</b>after</p></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.following_text("//b").unwrap(), "after");
+        assert_eq!(reader.following_text("//p").unwrap(), "");
+        assert_eq!(reader.following_text("//missing").unwrap(), "");
+    }
+
+    #[test]
+    fn node_text() {
+        let xml =
+            r#"<?xml version="1.0"?><root><p>Hello <b>bold</b> world</p><e><b>x</b></e></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let p = reader.with_nodeset_eval("//p").unwrap();
+
+        assert_eq!(p.node_text().unwrap(), "Hello ");
+        assert_eq!(p.read::<String, _>(".").unwrap(), "Hello bold world");
+        let e = reader.with_nodeset_eval("//e").unwrap();
+        assert_eq!(e.node_text().unwrap(), "");
+    }
+
+    #[test]
+    fn direct_text_nodes() {
+        let xml = r#"<?xml version="1.0"?><root><p>Hello <b>bold</b> world</p></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let p = reader.with_nodeset_eval("//p").unwrap();
+
+        assert_eq!(
+            p.direct_text_nodes().unwrap(),
+            vec!["Hello ".to_string(), " world".to_string()]
+        );
+    }
+
+    #[test]
+    fn read_comments() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><meta><!-- generated by X --><a/><!--version 2--></meta></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_comments("//meta").unwrap(),
+            vec![" generated by X ".to_string(), "version 2".to_string()]
+        );
+        assert!(reader.read_comments("//a").unwrap().is_empty());
+        assert!(reader.read_comments("//missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_processing_instructions() {
+        let xml = r#"<?xml version="1.0"?>
+                     <?xml-stylesheet href="style.xsl" type="text/xsl"?>
+                     <root><?marker?></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_processing_instructions().unwrap(),
+            vec![
+                (
+                    "xml-stylesheet".to_string(),
+                    r#"href="style.xsl" type="text/xsl""#.to_string()
+                ),
+                ("marker".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn read_matching() {
+        let xml = r#"<?xml version="1.0"?><root><zip>8001</zip><bad>80O1</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let pattern = Regex::new(r"^\d{4}$").unwrap();
+
+        assert_eq!(reader.read_matching("//zip", &pattern).unwrap(), "8001");
+        let err = reader.read_matching("//bad", &pattern).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn grouped_number() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><us>1,234,567.89</us><eu> 1.234.567,89 </eu><bad>1,2a</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_grouped_number("//us", ',', '.').unwrap(),
+            1234567.89
+        );
+        assert_eq!(
+            reader.read_grouped_number("//eu", '.', ',').unwrap(),
+            1234567.89
+        );
+        assert!(reader.read_grouped_number("//us", '\'', ',').is_err());
+        let err = reader.read_grouped_number("//bad", '.', ',').err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn byte_list() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><dec>255 0  128</dec><hex> ff 0A 7f </hex><big>1 256</big><empty/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_byte_list("//dec", 10).unwrap(),
+            vec![255, 0, 128]
+        );
+        assert_eq!(
+            reader.read_byte_list("//hex", 16).unwrap(),
+            vec![0xff, 0x0a, 0x7f]
+        );
+        assert!(reader.read_byte_list("//empty", 10).unwrap().is_empty());
+        let err = reader.read_byte_list("//big", 10).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(reader.read_byte_list("//hex", 10).is_err());
+    }
+
+    #[test]
+    fn percentage() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><a>73%</a><b> 12.5 </b><c>101%</c><d>-1</d><e>abc%</e><f>%</f></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read_percentage("//a").unwrap(), 73.0);
+        assert_eq!(reader.read_percentage("//b").unwrap(), 12.5);
+        for xpath in &["//c", "//d", "//e", "//f"] {
+            let err = reader.read_percentage(*xpath).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::FromXml);
+        }
+    }
+
+    #[test]
+    fn xsd_bool() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><b>true</b><b>false</b><b> 1 </b><b>0</b><b>yes</b><b>True</b></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let read = |i| reader.read_xsd_bool(format!("//b[{}]", i).as_str());
+        assert!(read(1).unwrap());
+        assert!(!read(2).unwrap());
+        assert!(read(3).unwrap());
+        assert!(!read(4).unwrap());
+        assert_eq!(read(5).err().unwrap().kind(), ErrorKind::FromXml);
+        assert!(read(6).is_err());
+    }
+
+    #[test]
+    fn required_number() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><price> 42 </price><rate>0.5</rate><empty/><blank> </blank><bad>4x</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_required_number::<u32, _>("//price").unwrap(),
+            42
+        );
+        assert_eq!(
+            reader.read_required_number::<f64, _>("//rate").unwrap(),
+            0.5
+        );
+        for xpath in &["//empty", "//blank", "//absent"] {
+            let err = reader.read_required_number::<f64, _>(*xpath).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::MissingValue);
+        }
+        let err = reader
+            .read_required_number::<u32, _>("//bad")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn iso_duration() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><a>PT1H30M</a><b>PT45S</b><c>P1DT0.5S</c><d>P2D</d>
+                     <y>P1Y</y><m>P1M</m><e>PT</e><f>PT1M1H</f><g>PT5</g>
+                     <h>PT99999999999999999999.5S</h><i>PT1..5S</i></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let duration = |xpath| reader.read_iso_duration(xpath).unwrap();
+        assert_eq!(duration("//a"), Duration::from_secs(5400));
+        assert_eq!(duration("//b"), Duration::from_secs(45));
+        assert_eq!(duration("//c"), Duration::from_millis(86_400_500));
+        assert_eq!(duration("//d"), Duration::from_secs(172_800));
+
+        let err = reader.read_iso_duration("//y").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(err
+            .to_string()
+            .contains("Unsupported duration component 'Y'"));
+        assert!(reader.read_iso_duration("//m").is_err());
+        assert!(reader.read_iso_duration("//e").is_err());
+        assert!(reader.read_iso_duration("//f").is_err());
+        assert!(reader.read_iso_duration("//g").is_err());
+        assert!(reader.read_iso_duration("//h").is_err());
+        assert!(reader.read_iso_duration("//i").is_err());
+    }
+
+    #[test]
+    fn enum_list() {
+        #[derive(Debug, PartialEq)]
+        enum Role {
+            Admin,
+            Editor,
+        }
+
+        impl str::FromStr for Role {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    "admin" => Ok(Role::Admin),
+                    "editor" => Ok(Role::Editor),
+                    _ => Err(format!("unknown role {}", s)),
+                }
+            }
+        }
+
+        let xml = r#"<?xml version="1.0"?>
+                     <root><roles>admin, editor,</roles><bad>admin,root,guest</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let roles: Vec<Role> = reader.read_enum_list("//roles", ",").unwrap();
+        assert_eq!(roles, vec![Role::Admin, Role::Editor]);
+        let err = reader
+            .read_enum_list::<Role, _>("//bad", ",")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        let msg = format!("{}", err);
+        assert!(msg.contains("'root'"));
+        assert!(msg.contains("'guest'"));
     }
-}
 
-from_parse_str!(f32, f64, u8, u16, u32, u64, i8, i16, i32, i64, bool);
+    #[test]
+    fn count_distinct() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><tag>a</tag><tag>b</tag><tag>a</tag><tag>c</tag><tag>b</tag></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(reader.count_distinct("//tag").unwrap(), 3);
+        assert_eq!(reader.count_distinct("//absent").unwrap(), 0);
+    }
 
     #[test]
-    fn xpath_str_reader() {
-        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
-                     <root><child name="Hello World"/></root>"#;
+    fn distinct_attr_values() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><item category="books"/><item category="music"/><item/>
+                     <item category="books"/><item category="games"/><item category="music"/></root>"#;
         let reader = Reader::from_str(xml, None).unwrap();
+
         assert_eq!(
-            reader.evaluate(".//child/@name").unwrap().string(),
-            "Hello World".to_string()
+            reader.distinct_attr_values("//item", "category").unwrap(),
+            vec!["books", "music", "games"]
+        );
+        assert!(reader
+            .distinct_attr_values("//absent", "category")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn aggregates() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><n>3</n><n> -1.5 </n><n>10</n><n>2</n><bad>1</bad><bad>NaN</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.sum("//n").unwrap(), 13.5);
+        assert_eq!(reader.min("//n").unwrap(), Some(-1.5));
+        assert_eq!(reader.max("//n").unwrap(), Some(10.0));
+        assert_eq!(reader.sum("//absent").unwrap(), 0.0);
+        assert_eq!(reader.max("//absent").unwrap(), None);
+        let err = reader.sum("//bad").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn unique_keys() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><users><user id="b"/><user id="a"/></users>
+                     <groups><group><name>x</name></group><group><name>x</name></group></groups></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_unique_keys("//user", "@id").unwrap(),
+            vec!["b".to_string(), "a".to_string()]
+        );
+        let err = reader.read_unique_keys("//group", "name").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(err.to_string().contains("'x'"));
+    }
+
+    #[test]
+    fn ipv4_and_cidr() {
+        let xml = r#"<?xml version="1.0"?>
+                     <net><gw>10.0.0.1</gw><net>10.0.0.0/8</net><bad>10.0.0.0/33</bad></net>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let gw: Ipv4Addr = reader.read("//gw").unwrap();
+        assert_eq!(gw, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(
+            reader.read_cidr("//net/net").unwrap(),
+            (Ipv4Addr::new(10, 0, 0, 0), 8)
+        );
+        let err = reader.read_cidr("//bad").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(reader.read_cidr("//gw").is_err());
+    }
+
+    #[test]
+    fn evaluate_usize() {
+        let xml = r#"<?xml version="1.0"?><root><a/><a/><a/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.evaluate_usize("count(//a)").unwrap(), 3);
+        assert_eq!(reader.evaluate_usize("count(//b)").unwrap(), 0);
+        let err = reader.evaluate_usize("count(//a) div 2").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        let err = reader.evaluate_usize("-count(//a)").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(reader.evaluate_usize("number('x')").is_err());
+    }
+
+    #[test]
+    fn read_lower_upper() {
+        let xml = r#"<?xml version="1.0"?><root><name>Hello Wörld</name></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read_lower("//name").unwrap(), "hello wörld");
+        assert_eq!(reader.read_upper("//name").unwrap(), "HELLO WöRLD");
+        assert_eq!(reader.read_upper("//inexistent").unwrap(), "");
+        assert_eq!(
+            reader.read_lower("concat(//name, '!')").unwrap(),
+            "hello wörld!"
         );
+
+        let xpath = sxd_xpath::Factory::new().build("//name").unwrap().unwrap();
+        assert_eq!(reader.read_lower(&xpath).unwrap(), "hello wörld");
+        assert_eq!(reader.read_upper(xpath).unwrap(), "HELLO WöRLD");
+    }
+
+    #[test]
+    fn read_ci() {
+        let xml = r#"<?xml version="1.0"?><HTML><Head><Title>Page</Title></Head></HTML>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let title = reader.read_ci("title").unwrap();
+        assert_eq!(title.read::<String, _>(".").unwrap(), "Page");
+        let head = reader.read_ci("HEAD").unwrap();
+        assert_eq!(head.anchor_nodeset().size(), 1);
+        assert!(reader.read_ci("body").unwrap().anchor_node().is_none());
+        assert!(reader.read_ci("it's").unwrap().anchor_node().is_none());
     }
 
     #[test]
@@ -342,6 +3075,83 @@ mod tests {
         assert_eq!(Option::<String>::from_xml(&inexistent).unwrap(), None);
     }
 
+    #[test]
+    fn string_from_cdata() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><code><![CDATA[a < b]]></code><mixed>x <![CDATA[&& y]]></mixed></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read::<String, _>("//code").unwrap(), "a < b");
+        assert_eq!(reader.read::<String, _>("//mixed").unwrap(), "x && y");
+    }
+
+    #[test]
+    fn string_from_char_refs() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><dec>&#65;&#228;</dec><hex a="&#x41;">&#x41;&#x1F600;</hex><pre>&lt;&amp;&quot;</pre></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read::<String, _>("//dec").unwrap(), "A\u{e4}");
+        assert_eq!(reader.read::<String, _>("//hex").unwrap(), "A\u{1f600}");
+        assert_eq!(reader.read::<String, _>("//hex/@a").unwrap(), "A");
+        assert_eq!(reader.read::<String, _>("//pre").unwrap(), "<&\"");
+    }
+
+    #[test]
+    fn kv() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><entry><k>color</k><v>red</v></entry><entry><k>size</k><v>3</v></entry></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let entry = reader.with_nodeset_eval("//entry[1]").unwrap();
+        let (key, value): (String, String) = entry.read_kv("k", "v").unwrap();
+        assert_eq!(key, "color");
+        assert_eq!(value, "red");
+        let entry = reader.with_nodeset_eval("//entry[2]").unwrap();
+        assert_eq!(
+            entry.read_kv::<String, u32>("k", "v").unwrap(),
+            ("size".to_string(), 3)
+        );
+    }
+
+    #[test]
+    fn string_or() {
+        let xml = r#"<?xml version="1.0"?><root><title>Hello</title><empty/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read_string_or("//title", "none").unwrap(), "Hello");
+        assert_eq!(reader.read_string_or("//empty", "none").unwrap(), "");
+        assert_eq!(reader.read_string_or("//absent", "none").unwrap(), "none");
+    }
+
+    #[test]
+    fn lines() {
+        let xml = "<?xml version=\"1.0\"?><root><pre>fn main() {\n\n    run();\r\n}\n</pre><one>x</one></root>";
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(
+            reader.read_lines("//pre").unwrap(),
+            vec!["fn main() {", "", "    run();", "}"]
+        );
+        assert_eq!(reader.read_lines("//one").unwrap(), vec!["x"]);
+    }
+
+    #[test]
+    fn first_nonempty() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><description> </description><summary> Short </summary><empty/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let candidates = ["//absent", "//description", "//summary"];
+        assert_eq!(reader.read_first_nonempty(&candidates).unwrap(), "Short");
+        let err = reader
+            .read_first_nonempty(&["//absent", "//description", "//empty"])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        assert!(reader.read_first_nonempty(&[]).is_err());
+    }
+
     #[test]
     fn num_from_xml() {
         let xml = r#"<?xml version="1.0"?><root><float>-23.85</float><int>42</int></root>"#;
@@ -366,6 +3176,56 @@ mod tests {
         assert_eq!(i64::from_xml(&int).unwrap(), 42i64);
     }
 
+    #[test]
+    fn float_from_xml() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><sci>1.5e10</sci><inf>inf</inf><neg>-inf</neg><nan>NaN</nan><bad>1,5</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(reader.read::<f64, _>("//sci").unwrap(), 1.5e10);
+        assert_eq!(reader.read::<f64, _>("//inf").unwrap(), f64::INFINITY);
+        assert_eq!(reader.read::<f32, _>("//neg").unwrap(), f32::NEG_INFINITY);
+        assert!(reader.read::<f64, _>("//nan").unwrap().is_nan());
+        assert!(reader.read::<f64, _>("//bad").is_err());
+    }
+
+    #[test]
+    fn num_conversion_error() {
+        use std::convert::TryFrom;
+
+        fn read_u8(reader: &Reader, xpath: &str) -> Result<u8, Error> {
+            let value: u32 = reader.read(xpath)?;
+            Ok(u8::try_from(value)?)
+        }
+
+        let xml = r#"<?xml version="1.0"?><root><small>42</small><big>300</big></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        assert_eq!(read_u8(&reader, "//small").unwrap(), 42);
+        let err = read_u8(&reader, "//big").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        let err = reader.read::<u8, _>("//big").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn decimal_from_xml() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><price> 0.10 </price><sum>0.30</sum><bad>1.2.3</bad></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let price: Decimal = reader.read("//price").unwrap();
+        assert_eq!(
+            price * Decimal::from(3),
+            reader.read::<Decimal, _>("//sum").unwrap()
+        );
+        assert_eq!(reader.read::<Option<Decimal>, _>("//absent").unwrap(), None);
+
+        let err = reader.read::<Decimal, _>("//bad").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
     #[test]
     fn num_absent() {
         let xml = r#"<?xml version="1.0"?><root><float>-23.85</float><int>42</int></root>"#;
@@ -379,6 +3239,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn bool_from_xml() {
         let xml = r#"<?xml version="1.0"?><root><t>true</t><f>false</f></root>"#;
         let reader = Reader::from_str(xml, None).unwrap();
@@ -399,6 +3260,620 @@ mod tests {
         assert_eq!(tags, vec!["cyberpunk".to_string(), "sci-fi".to_string()]);
     }
 
+    #[test]
+    fn vec_deque() {
+        let xml = r#"<?xml version="1.0"?><book><tags><tag name="cyberpunk"/><tag name="sci-fi"/></tags></book>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let vec: Vec<String> = reader.read("//book/tags/tag/@name").unwrap();
+        let deque: VecDeque<String> = reader.read("//book/tags/tag/@name").unwrap();
+        assert_eq!(deque, vec);
+    }
+
+    #[test]
+    fn btree_set() {
+        let xml = r#"<?xml version="1.0"?><root><tag>sci-fi</tag><tag>cyberpunk</tag><tag>sci-fi</tag><tag>noir</tag></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let tags: BTreeSet<String> = reader.read("//tag").unwrap();
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        assert_eq!(tags, vec!["cyberpunk", "noir", "sci-fi"]);
+        assert!(reader
+            .read::<BTreeSet<String>, _>("//absent")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[derive(Debug)]
+    struct Range {
+        min: i32,
+        max: i32,
+    }
+
+    impl FromXml for Range {
+        fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+            Ok(Range {
+                min: reader.read("@min")?,
+                max: reader.read("@max")?,
+            })
+        }
+    }
+
+    impl ValidateXml for Range {
+        fn validate(&self) -> Result<(), Error> {
+            if self.min <= self.max {
+                Ok(())
+            } else {
+                Err(Error::custom_msg(format!(
+                    "min {} is greater than max {}",
+                    self.min, self.max
+                )))
+            }
+        }
+    }
+
+    #[test]
+    fn read_ctx() {
+        let xml = r#"<?xml version="1.0"?><root><item><price>abc</price></item></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let item = reader.with_nodeset_eval("//item").unwrap();
+
+        let err = item.read_ctx::<u32, _>("price", "price").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(err.message().starts_with("while reading field 'price': "));
+        assert!(err.to_string().contains("while reading field 'price': "));
+        assert_eq!(item.read_ctx::<String, _>("price", "price").unwrap(), "abc");
+    }
+
+    #[test]
+    fn read_validated() {
+        let xml =
+            r#"<?xml version="1.0"?><root><range min="1" max="5"/><range min="9" max="2"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let range: Range = reader.read_validated("//range[1]").unwrap();
+        assert_eq!((range.min, range.max), (1, 5));
+        assert!(reader.read::<Range, _>("//range[2]").is_ok());
+        let err = reader
+            .read_validated::<Range, _>("//range[2]")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("min 9 is greater than max 2"));
+        assert_eq!(err.expression(), Some("//range[2]"));
+    }
+
+    #[test]
+    fn read_scalar() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><url>https://example.com/feed?page=2</url><title>Neuromancer</title></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let base: String = reader.read("substring-before(//url, '?')").unwrap();
+        assert_eq!(base, "https://example.com/feed");
+        assert_eq!(reader.read::<u32, _>("string-length(//title)").unwrap(), 11);
+        assert_eq!(reader.read::<f64, _>("count(//title) div 4").unwrap(), 0.25);
+        assert!(reader.read::<bool, _>("boolean(//title)").unwrap());
+        assert_eq!(
+            reader
+                .read::<Option<String>, _>("concat(//title, '!')")
+                .unwrap(),
+            Some("Neuromancer!".to_string())
+        );
+        assert!(reader.read::<u32, _>("string(//title)").is_err());
+    }
+
+    #[test]
+    fn read_scalar_reader() {
+        #[derive(Debug, PartialEq)]
+        struct Inspect {
+            is_root: bool,
+            has_package: bool,
+            value: Option<String>,
+            title: Option<String>,
+        }
+
+        impl FromXml for Inspect {
+            fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+                Ok(Inspect {
+                    is_root: reader.is_root(),
+                    has_package: reader.package().is_some(),
+                    value: reader.read("value")?,
+                    title: reader.read("//title")?,
+                })
+            }
+        }
+
+        let xml = r#"<?xml version="1.0"?><root><title>Neuromancer</title></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let scalar: Inspect = reader.read("count(//title)").unwrap();
+        assert_eq!(
+            scalar,
+            Inspect {
+                is_root: true,
+                has_package: true,
+                value: Some("1".to_string()),
+                title: None,
+            }
+        );
+        let node: Inspect = reader.read("/root").unwrap();
+        assert_eq!(
+            node,
+            Inspect {
+                is_root: false,
+                has_package: false,
+                value: None,
+                title: Some("Neuromancer".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn map_value() {
+        let xml = r#"<?xml version="1.0"?><root><n>21</n></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let doubled = reader.read_map_value("//n", |n: u32| n * 2).unwrap();
+        assert_eq!(doubled, 42);
+        assert!(reader.read_map_value("//n", |n: bool| !n).is_err());
+    }
+
+    #[test]
+    fn nodes() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><item><price>3</price></item><item><price>4</price></item><item><price>5</price></item></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let total: Result<u32, Error> = reader
+            .nodes("//item")
+            .unwrap()
+            .map(|item| item.read::<u32, _>("price"))
+            .sum();
+        assert_eq!(total.unwrap(), 12);
+        assert_eq!(reader.nodes("//item").unwrap().skip(1).count(), 2);
+        assert_eq!(reader.nodes("//absent").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn vec_results() {
+        let xml = r#"<?xml version="1.0"?><root><n>1</n><n>two</n><n>3</n></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let results: Vec<Result<u32, Error>> = reader.read_vec_results("//n").unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &3);
+    }
+
+    #[test]
+    fn read_into() {
+        let xml = r#"<?xml version="1.0"?><root><a>1</a><a>2</a><b>3</b></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let mut values = Vec::new();
+        reader.read_into::<_, u32, _>("//a", &mut values).unwrap();
+        reader.read_into::<_, u32, _>("//b", &mut values).unwrap();
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn vec_sorted_by() {
+        let xml = r#"<?xml version="1.0"?><root><n>3</n><n>-10</n><n>1</n><n>-2</n></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let sorted: Vec<i32> = reader.read_vec_sorted_by("//n", |n: &i32| *n).unwrap();
+        assert_eq!(sorted, vec![-10, -2, 1, 3]);
+        let by_abs: Vec<i32> = reader.read_vec_sorted_by("//n", |n: &i32| n.abs()).unwrap();
+        assert_eq!(by_abs, vec![1, -2, 3, -10]);
+    }
+
+    #[test]
+    fn vec_limited() {
+        let xml = r#"<?xml version="1.0"?><root><n>1</n><n>2</n><n>3</n></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let all: Vec<u32> = reader.read_vec_limited("//n", 3, false).unwrap();
+        assert_eq!(all, vec![1, 2, 3]);
+        let truncated: Vec<u32> = reader.read_vec_limited("//n", 2, true).unwrap();
+        assert_eq!(truncated, vec![1, 2]);
+        let err = reader
+            .read_vec_limited::<u32, _>("//n", 2, false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    }
+
+    #[test]
+    fn interned_vec() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><c>books</c><c>music</c><c>books</c><d>music</d></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let mut interner = HashSet::new();
+
+        let c = reader.read_interned_vec("//c", &mut interner).unwrap();
+        assert_eq!(
+            c.iter().map(|s| &**s).collect::<Vec<_>>(),
+            vec!["books", "music", "books"]
+        );
+        assert!(Rc::ptr_eq(&c[0], &c[2]));
+        let d = reader.read_interned_vec("//d", &mut interner).unwrap();
+        assert!(Rc::ptr_eq(&c[1], &d[0]));
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn dispatched_vec() {
+        #[derive(Debug, PartialEq)]
+        enum Block {
+            Para(String),
+            Img(String),
+        }
+
+        let xml = r#"<?xml version="1.0"?>
+                     <body><para>Intro</para><img src="a.png"/><para>Text</para></body>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let blocks = reader
+            .read_dispatched_vec("/body/*", |name, reader| match name {
+                "para" => Ok(Block::Para(reader.read(".")?)),
+                "img" => Ok(Block::Img(reader.read("@src")?)),
+                _ => Err(Error::custom_msg("Unknown block.")),
+            })
+            .unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Para("Intro".to_string()),
+                Block::Img("a.png".to_string()),
+                Block::Para("Text".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><task priority="5"><active/></task><task priority="1" disabled=""/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let first = reader.with_nodeset_eval("//task[1]").unwrap();
+        let second = reader.with_nodeset_eval("//task[2]").unwrap();
+
+        assert!(first.matches("active").unwrap());
+        assert!(!second.matches("active").unwrap());
+        let predicate = "@priority > 3 and not(@disabled)";
+        assert!(first.matches(predicate).unwrap());
+        assert!(!second.matches(predicate).unwrap());
+    }
+
+    #[test]
+    fn attr_present() {
+        let xml = r#"<?xml version="1.0"?><root><item disabled=""/><item/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let disabled = reader.with_nodeset_eval("//item[1]").unwrap();
+        assert!(disabled.read_attr_present("disabled").unwrap());
+        let enabled = reader.with_nodeset_eval("//item[2]").unwrap();
+        assert!(!enabled.read_attr_present("disabled").unwrap());
+    }
+
+    #[test]
+    fn option_attr() {
+        let xml = r#"<?xml version="1.0"?><root><item empty="" blank="  " id="7"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let item = reader.with_nodeset_eval("//item").unwrap();
+
+        assert_eq!(item.read_option_attr::<u32>("absent").unwrap(), None);
+        assert_eq!(item.read_option_attr::<u32>("empty").unwrap(), None);
+        assert_eq!(item.read_option_attr::<String>("blank").unwrap(), None);
+        assert_eq!(item.read_option_attr::<u32>("id").unwrap(), Some(7));
+    }
+
+    #[test]
+    fn attr_or() {
+        let xml = r#"<?xml version="1.0"?><root><item empty="" id="7" bad="x"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let item = reader.with_nodeset_eval("//item").unwrap();
+
+        assert_eq!(item.read_attr_or("id", 1u32).unwrap(), 7);
+        assert_eq!(item.read_attr_or("empty", 1u32).unwrap(), 1);
+        assert_eq!(item.read_attr_or("absent", 1u32).unwrap(), 1);
+        assert!(item.read_attr_or("bad", 1u32).is_err());
+    }
+
+    #[test]
+    fn present_one_of() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><task><done/></task><task/><task><pending/><failed/></task></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let names = ["pending", "done", "failed"];
+
+        let task = reader.with_nodeset_eval("//task[1]").unwrap();
+        assert_eq!(task.read_present_one_of(&names).unwrap(), "done");
+        let task = reader.with_nodeset_eval("//task[2]").unwrap();
+        let err = task.read_present_one_of(&names).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        let task = reader.with_nodeset_eval("//task[3]").unwrap();
+        let err = task.read_present_one_of(&names).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn read_pairs() {
+        let xml = r#"<?xml version="1.0"?>
+                     <headers><h name="Accept">text/html</h><h name="Via">a</h><h name="Via">b</h></headers>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let pairs: Vec<(String, String)> = reader.read_pairs("//h", "@name", ".").unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("Accept".to_string(), "text/html".to_string()),
+                ("Via".to_string(), "a".to_string()),
+                ("Via".to_string(), "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_typed_map() {
+        let xml = r#"<?xml version="1.0"?>
+                     <stock><item sku="a"><qty>3</qty></item><item sku="b"><qty>10</qty></item>
+                     <item sku="a"><qty>5</qty></item></stock>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let map: HashMap<String, u32> = reader.read_typed_map("//item", "@sku", "qty").unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a"], 5);
+        assert_eq!(map["b"], 10);
+
+        let empty: HashMap<String, u32> = reader.read_typed_map("//none", "@sku", "qty").unwrap();
+        assert!(empty.is_empty());
+        assert!(reader
+            .read_typed_map::<u32>("//item", "@sku", "@sku")
+            .is_err());
+    }
+
+    #[test]
+    fn element_set() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        enum Permission {
+            Read,
+            Write,
+        }
+
+        fn permission(name: &str) -> Option<Permission> {
+            match name {
+                "read" => Some(Permission::Read),
+                "write" => Some(Permission::Write),
+                _ => None,
+            }
+        }
+
+        let xml = r#"<?xml version="1.0"?><permissions><read/><write/><delete/></permissions>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let permissions = reader.with_nodeset_eval("/permissions").unwrap();
+
+        let set = permissions.read_element_set(permission, true).unwrap();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&Permission::Read));
+        assert!(set.contains(&Permission::Write));
+
+        let err = permissions
+            .read_element_set(permission, false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+    }
+
+    #[test]
+    fn option_vec() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><full><tag>a</tag><tag>b</tag></full><empty/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let full: Option<Vec<String>> = reader.read_option_vec("//full", "tag").unwrap();
+        assert_eq!(full, Some(vec!["a".to_string(), "b".to_string()]));
+        let empty: Option<Vec<String>> = reader.read_option_vec("//empty", "tag").unwrap();
+        assert_eq!(empty, Some(vec![]));
+        let absent: Option<Vec<String>> = reader.read_option_vec("//absent", "tag").unwrap();
+        assert_eq!(absent, None);
+    }
+
+    #[test]
+    fn required_attr() {
+        let xml = r#"<?xml version="1.0"?><root><item id="42"/></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let item = reader.with_nodeset_eval("//item").unwrap();
+
+        assert_eq!(item.read_required_attr::<u32>("id").unwrap(), 42);
+        let err = item.read_required_attr::<u32>("name").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        assert!(err.to_string().contains("'name'"));
+    }
+
+    #[test]
+    fn attr_and_text() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><item id="5">Widget</item><item id="6"/><item>Gadget</item></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let item = reader.with_nodeset_eval("//item[1]").unwrap();
+        let (id, text): (u32, String) = item.read_attr_and_text("id").unwrap();
+        assert_eq!(id, 5);
+        assert_eq!(text, "Widget");
+
+        let item = reader.with_nodeset_eval("//item[2]").unwrap();
+        let (id, text): (u32, String) = item.read_attr_and_text("id").unwrap();
+        assert_eq!(id, 6);
+        assert_eq!(text, "");
+
+        let item = reader.with_nodeset_eval("//item[3]").unwrap();
+        let err = item.read_attr_and_text::<u32>("id").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn attr_at() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><service><config value="8080"/><name>web</name></service></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+        let service = reader.with_nodeset_eval("//service").unwrap();
+
+        assert_eq!(
+            service.read_attr_at::<u16, _>("config", "value").unwrap(),
+            8080
+        );
+        let err = service
+            .read_attr_at::<u16, _>("missing", "value")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+        assert!(err.to_string().contains("'missing'"));
+        let err = service
+            .read_attr_at::<String, _>("name", "value")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn if_attr() {
+        let xml = r#"<?xml version="1.0"?>
+                     <root><price type="net">10</price><tax type="gross">2</tax><fee>1</fee></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let read = |xpath| reader.read_if_attr::<u32, _>(xpath, "type", "net").unwrap();
+        assert_eq!(read("//price"), Some(10));
+        assert_eq!(read("//tax"), None);
+        assert_eq!(read("//fee"), None);
+        assert_eq!(read("//missing"), None);
+    }
+
+    #[derive(Debug)]
+    struct Tree {
+        children: Vec<Tree>,
+    }
+
+    impl FromXml for Tree {
+        fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+            Ok(Tree {
+                children: reader.read("node")?,
+            })
+        }
+    }
+
+    #[test]
+    fn expression_cache_shared() {
+        let xml = r#"<?xml version="1.0"?><root><book><title>A</title></book><book><title>B</title></book></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let books = reader.with_nodeset_eval("//book").unwrap();
+        assert!(Rc::ptr_eq(&reader.cache, &books.cache));
+        let titles: Vec<String> = books.read("title").unwrap();
+        assert_eq!(titles, vec!["A".to_string()]);
+
+        let second = reader.with_nodeset_eval("//book[2]").unwrap();
+        let size = RefCell::borrow(&reader.cache).len();
+        let cached = RefCell::borrow(&reader.cache)["title"].clone();
+        let titles: Vec<String> = second.read("title").unwrap();
+        assert_eq!(titles, vec!["B".to_string()]);
+        assert_eq!(RefCell::borrow(&reader.cache).len(), size);
+        assert!(Rc::ptr_eq(
+            &cached,
+            &RefCell::borrow(&second.cache)["title"]
+        ));
+    }
+
+    #[test]
+    fn expression_cache_bounded() {
+        let items: String = (0..300)
+            .map(|i| format!(r#"<item id="{}">{}</item>"#, i, i))
+            .collect();
+        let xml = format!(r#"<?xml version="1.0"?><root>{}</root>"#, items);
+        let reader = Reader::from_str(&xml, None).unwrap();
+
+        for i in 0..300 {
+            let id = i.to_string();
+            let item = reader
+                .with_nodeset_eval_by_attr("//item", "id", &id)
+                .unwrap();
+            assert_eq!(item.read::<u32, _>(".").unwrap(), i);
+            assert!(RefCell::borrow(&reader.cache).len() <= MAX_CACHED_EXPRESSIONS);
+        }
+    }
+
+    #[test]
+    fn with_variable() {
+        let xml = r#"<?xml version="1.0"?><root><item>a</item><item>b</item></root>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let item = reader.with_variable("pos", 2.0, |reader| {
+            reader.read::<String, _>("//item[$pos]").unwrap()
+        });
+        assert_eq!(item, "b");
+        assert!(reader.read::<String, _>("//item[$pos]").is_err());
+    }
+
+    #[test]
+    fn register_default_namespace() {
+        let xml = r#"<?xml version="1.0"?>
+                     <feed xmlns="http://www.w3.org/2005/Atom"><entry><title>A</title></entry></feed>"#;
+        let context = Context::new();
+        let mut reader = Reader::from_str(xml, Some(&context)).unwrap();
+        assert!(reader
+            .read::<Option<String>, _>("//title")
+            .unwrap()
+            .is_none());
+
+        let uri = reader.register_default_namespace("atom");
+        assert_eq!(uri.as_deref(), Some("http://www.w3.org/2005/Atom"));
+        let title: String = reader.read("//atom:entry/atom:title").unwrap();
+        assert_eq!(title, "A");
+
+        let xml = r#"<?xml version="1.0"?><root><item/></root>"#;
+        let mut reader = Reader::from_str(xml, None).unwrap();
+        assert_eq!(reader.register_default_namespace("x"), None);
+    }
+
+    #[test]
+    fn max_depth() {
+        let xml = r#"<?xml version="1.0"?>
+                     <node><node><node><node><node/></node></node></node></node>"#;
+        let mut reader = Reader::from_str(xml, None).unwrap();
+        let tree: Tree = reader.read("node").unwrap();
+        assert_eq!(tree.children.len(), 1);
+
+        reader.set_max_depth(Some(6));
+        assert!(reader.read::<Tree, _>("node").is_ok());
+
+        reader.set_max_depth(Some(3));
+        let err = reader.read::<Tree, _>("node").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+    }
+
+    #[test]
+    fn max_depth_result_readers() {
+        let xml = r#"<?xml version="1.0"?><root><a/><b/></root>"#;
+        let mut reader = Reader::from_str(xml, None).unwrap();
+        reader.set_max_depth(Some(1));
+        let root = reader.with_nodeset_eval("root").unwrap();
+
+        let depth_exceeded = |result: Result<(), Error>| {
+            result.err().map(|e| e.kind()) == Some(ErrorKind::DepthLimitExceeded)
+        };
+        assert!(depth_exceeded(root.with_nodeset_eval("a").map(|_| ())));
+        assert!(depth_exceeded(root.nodes("*").map(|_| ())));
+        assert!(depth_exceeded(root.child_readers().map(|_| ())));
+        assert!(depth_exceeded(root.descendant_readers("a").map(|_| ())));
+        assert!(depth_exceeded(
+            root.read_pairs::<String, String>("*", ".", ".").map(|_| ())
+        ));
+
+        assert_eq!(reader.nodes("root/*").unwrap().count(), 2);
+        assert_eq!(reader.child_readers().unwrap().len(), 1);
+    }
+
     #[test]
     fn vec_non_existent() {
         let xml = r#"<?xml version="1.0"?><root><t>true</t><f>false</f></root>"#;
@@ -0,0 +1,207 @@
+// Copyright 2018 Leonardo Schwarz <mail@leoschwarz.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Chunked processing of large documents.
+//!
+//! sxd_document always builds the complete document tree in memory, which
+//! can be expensive for huge documents consisting of many repeated
+//! elements, like feeds or database dumps. The functions in this module
+//! approximate streaming by parsing each child of the root element on its
+//! own, so only the tree of one child is kept in memory at a time (in
+//! addition to the input string itself).
+
+use context::Context;
+use errors::{Error, ErrorKind};
+use expression;
+use reader::Reader;
+use util::{find_from, tag_end};
+
+/// Calls `f` for every node matching the XPath expression, parsing the
+/// document one child of the root element at a time.
+///
+/// Each child of the root element is wrapped in a copy of the root start
+/// tag, so namespace declarations and attributes of the root element stay
+/// available, and the expression is evaluated against this partial
+/// document. For the typical expression selecting repeated elements, like
+/// `/feed/entry`, this yields the same nodes as evaluating it against the
+/// whole document. Expressions relating nodes from different children
+/// (e.g. positional predicates like `entry[2]`) however will behave
+/// differently, as will anything declared in a DTD.
+///
+/// Errors returned by `f` abort the processing and are returned.
+///
+/// # Examples
+/// ```
+/// use xpath_reader::stream::for_each_matching;
+///
+/// let xml = r#"<?xml version="1.0"?><items><item>1</item><item>2</item></items>"#;
+///
+/// let mut sum = 0;
+/// for_each_matching(xml, "/items/item", None, |reader| {
+///     sum += reader.read::<u32, _>(".")?;
+///     Ok(())
+/// }).unwrap();
+/// assert_eq!(sum, 3);
+/// ```
+pub fn for_each_matching<'c, F>(
+    xml: &str,
+    xpath_expr: &str,
+    context: Option<&'c Context<'c>>,
+    mut f: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&Reader) -> Result<(), Error>,
+{
+    let xpath = expression::parse(xpath_expr)?;
+    let (root_tag, root_name, children) = split_root_children(xml)?;
+
+    for child in children {
+        let chunk = format!("{}{}</{}>", root_tag, child, root_name);
+        let reader = Reader::from_str(&chunk, context)?;
+        let nodes = reader.with_nodeset_eval(&xpath)?;
+        for node in nodes.anchor_nodeset().document_order() {
+            f(&Reader::from_node(node, context))?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits the document into the start tag of the root element, the name
+/// of the root element and the source of all child elements of the root.
+fn split_root_children(xml: &str) -> Result<(&str, &str, Vec<&str>), Error> {
+    let mut pos = 0;
+    let root_start = loop {
+        let start = find_from(xml, "<", pos).ok_or_else(|| parse_error("Missing root element."))?;
+        match skip_misc(xml, start)? {
+            Some(end) => pos = end,
+            None => break start,
+        }
+    };
+    let root_end =
+        tag_end(xml, root_start).ok_or_else(|| parse_error("Unexpected end of document."))?;
+    let root_tag = &xml[root_start..root_end];
+    let root_name = root_tag[1..]
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or("");
+    let mut children = Vec::new();
+    if root_tag.ends_with("/>") {
+        return Ok((root_tag, root_name, children));
+    }
+
+    let mut depth = 0;
+    let mut child_start = 0;
+    pos = root_end;
+    loop {
+        let start =
+            find_from(xml, "<", pos).ok_or_else(|| parse_error("Unexpected end of document."))?;
+        if let Some(end) = skip_misc(xml, start)? {
+            pos = end;
+            continue;
+        }
+        let end = tag_end(xml, start).ok_or_else(|| parse_error("Unexpected end of document."))?;
+        if xml[start..].starts_with("</") {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+            if depth == 0 {
+                children.push(&xml[child_start..end]);
+            }
+        } else {
+            if depth == 0 {
+                child_start = start;
+            }
+            if xml[..end].ends_with("/>") {
+                if depth == 0 {
+                    children.push(&xml[child_start..end]);
+                }
+            } else {
+                depth += 1;
+            }
+        }
+        pos = end;
+    }
+    Ok((root_tag, root_name, children))
+}
+
+/// If there is a comment, CDATA section, processing instruction or doctype
+/// declaration at `start`, returns the position after it.
+fn skip_misc(xml: &str, start: usize) -> Result<Option<usize>, Error> {
+    let rest = &xml[start..];
+    let terminator = if rest.starts_with("<!--") {
+        "-->"
+    } else if rest.starts_with("<![CDATA[") {
+        "]]>"
+    } else if rest.starts_with("<?") {
+        "?>"
+    } else if rest.starts_with("<!") {
+        // Doctype declaration, possibly with an internal subset.
+        match (rest.find('['), rest.find('>')) {
+            (Some(bracket), Some(gt)) if bracket < gt => "]>",
+            _ => ">",
+        }
+    } else {
+        return Ok(None);
+    };
+    find_from(xml, terminator, start)
+        .map(|end| Some(end + terminator.len()))
+        .ok_or_else(|| parse_error("Unexpected end of document."))
+}
+
+fn parse_error(msg: &'static str) -> Error {
+    Error::internal(msg, ErrorKind::ParseXml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_elements() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- A feed. -->
+            <feed xmlns="urn:feed" title="a > b">
+                <entry id="1"><title>First</title></entry>
+                <!-- <entry id="0"/> -->
+                <meta><![CDATA[<entry>]]></meta>
+                <entry id="2"><title>Second</title><entry id="nested"/></entry>
+                <entry id="3"/>
+            </feed>"#;
+        let mut context = Context::new();
+        context.set_namespace("f", "urn:feed");
+
+        let mut ids = Vec::new();
+        for_each_matching(xml, "/f:feed/f:entry", Some(&context), |reader| {
+            ids.push(reader.read::<String, _>("@id")?);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn callback_error() {
+        let xml = r#"<?xml version="1.0"?><items><item>1</item><item>x</item></items>"#;
+
+        let mut count = 0;
+        let result = for_each_matching(xml, "/items/item", None, |reader| {
+            reader.read::<u32, _>(".")?;
+            count += 1;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2018 Leonardo Schwarz <mail@leoschwarz.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Common value types with `FromXml` implementations.
+
+use errors::{Error, ErrorKind};
+use reader::{FromXml, FromXmlResult, Reader};
+
+/// A geographic coordinate in decimal degrees.
+///
+/// It is read from the attributes `lat` and `lon` of the anchor node,
+/// like `<point lat="51.5" lon="-0.12"/>`. Latitudes outside of `-90..=90`
+/// and longitudes outside of `-180..=180` result in an error of kind
+/// `ErrorKind::FromXml`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coordinate {
+    /// The latitude in degrees, positive values are north of the equator.
+    pub lat: f64,
+    /// The longitude in degrees, positive values are east of Greenwich.
+    pub lon: f64,
+}
+
+impl FromXml for Coordinate {
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+        let lat: f64 = reader.read_required_attr("lat")?;
+        let lon: f64 = reader.read_required_attr("lon")?;
+        if !(-90.0..=90.0).contains(&lat) {
+            return Err(Error::internal(
+                format!("Latitude {} out of range -90..=90.", lat),
+                ErrorKind::FromXml,
+            ));
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            return Err(Error::internal(
+                format!("Longitude {} out of range -180..=180.", lon),
+                ErrorKind::FromXml,
+            ));
+        }
+        Ok(Coordinate { lat, lon })
+    }
+}
+
+/// A color with 8 bit red, green and blue components.
+///
+/// It is read from hex notation, either `#rrggbb` or the shorthand `#rgb`
+/// which is equivalent to `#rrggbb` with each digit repeated. Values of any
+/// other form result in an error of kind `ErrorKind::FromXml`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Rgb {
+    /// The red component.
+    pub r: u8,
+    /// The green component.
+    pub g: u8,
+    /// The blue component.
+    pub b: u8,
+}
+
+impl FromXml for Rgb {
+    fn from_xml<'d>(reader: &'d Reader<'d>) -> FromXmlResult<Self> {
+        let value = String::from_xml(reader)?;
+        let invalid = || {
+            Error::internal(
+                format!("Invalid color, expected '#rrggbb' or '#rgb': '{}'", value),
+                ErrorKind::FromXml,
+            )
+        };
+        let hex = value.trim().strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let component = |digits: &str| u8::from_str_radix(digits, 16).unwrap();
+        match hex.len() {
+            6 => Ok(Rgb {
+                r: component(&hex[0..2]),
+                g: component(&hex[2..4]),
+                b: component(&hex[4..6]),
+            }),
+            3 => Ok(Rgb {
+                r: component(&hex[0..1]) * 0x11,
+                g: component(&hex[1..2]) * 0x11,
+                b: component(&hex[2..3]) * 0x11,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinate() {
+        let xml = r#"<?xml version="1.0"?>
+                     <map><point lat="51.5" lon="-0.12"/><point lat="91" lon="0"/>
+                     <point lat="0" lon="-180.5"/><point lat="0"/></map>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let london: Coordinate = reader.read("//point[1]").unwrap();
+        assert_eq!(
+            london,
+            Coordinate {
+                lat: 51.5,
+                lon: -0.12,
+            }
+        );
+        let err = reader.read::<Coordinate, _>("//point[2]").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        assert!(err.to_string().contains("Latitude 91"));
+        let err = reader.read::<Coordinate, _>("//point[3]").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::FromXml);
+        let err = reader.read::<Coordinate, _>("//point[4]").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingValue);
+    }
+
+    #[test]
+    fn rgb() {
+        let xml = r#"<?xml version="1.0"?>
+                     <ui><color>#ff8800</color><color> #F80 </color><color>#ff880</color>
+                     <color>#gg8800</color><color>ff8800</color></ui>"#;
+        let reader = Reader::from_str(xml, None).unwrap();
+
+        let orange = Rgb {
+            r: 0xff,
+            g: 0x88,
+            b: 0x00,
+        };
+        assert_eq!(reader.read::<Rgb, _>("//color[1]").unwrap(), orange);
+        assert_eq!(reader.read::<Rgb, _>("//color[2]").unwrap(), orange);
+        for i in 3..6 {
+            let xpath = format!("//color[{}]", i);
+            let err = reader.read::<Rgb, _>(xpath.as_str()).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::FromXml);
+        }
+    }
+}
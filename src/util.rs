@@ -1,4 +1,6 @@
+use errors::{Error, ErrorKind};
 use std::borrow::Borrow;
+use std::collections::HashMap;
 
 // TODO: Is there a standard type for this in Rust, like Cow but without
 //       the clone requirement.
@@ -10,9 +12,9 @@ pub(crate) enum Refable<'a, T: 'a> {
 
 impl<'a, T> Borrow<T> for Refable<'a, T> {
     fn borrow(&self) -> &T {
-        match self {
-            &Refable::Owned(ref v) => &v,
-            &Refable::Borrowed(v) => v,
+        match *self {
+            Refable::Owned(ref v) => v,
+            Refable::Borrowed(v) => v,
         }
     }
 }
@@ -22,3 +24,273 @@ impl<'a, T> Refable<'a, T> {
         Refable::Borrowed(self.borrow())
     }
 }
+
+impl<'a, T: Clone> Refable<'a, T> {
+    /// Returns a mutable reference, cloning a borrowed value first.
+    pub fn to_mut(&mut self) -> &mut T {
+        if let Refable::Borrowed(v) = *self {
+            *self = Refable::Owned(v.clone());
+        }
+        match *self {
+            Refable::Owned(ref mut v) => v,
+            Refable::Borrowed(_) => unreachable!(),
+        }
+    }
+}
+
+const PREDEFINED_ENTITIES: [&str; 5] = ["amp", "lt", "gt", "quot", "apos"];
+
+/// Replaces all references to custom entities in `xml` by their replacement
+/// text from `entities`.
+///
+/// Character references and the predefined XML entities are left untouched,
+/// as are comments, CDATA sections and processing instructions, where
+/// references are not recognized by XML.
+pub(crate) fn expand_entities(
+    xml: &str,
+    entities: &HashMap<String, String>,
+) -> Result<String, Error> {
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(['&', '<']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('<') {
+            let end = if rest.starts_with("<!--") {
+                rest.find("-->").map(|end| end + 3)
+            } else if rest.starts_with("<![CDATA[") {
+                rest.find("]]>").map(|end| end + 3)
+            } else if rest.starts_with("<?") {
+                rest.find("?>").map(|end| end + 2)
+            } else {
+                Some(1)
+            };
+            let end = end.unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let name = rest[1..].split(';').next().unwrap_or("");
+        let is_reference = rest[1..].contains(';')
+            && !name.is_empty()
+            && !name.starts_with('#')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':');
+
+        if !is_reference || PREDEFINED_ENTITIES.contains(&name) {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        }
+
+        match entities.get(name) {
+            Some(replacement) => out.push_str(replacement),
+            None => {
+                return Err(Error::internal(
+                    format!("Undefined entity: '&{};'", name),
+                    ErrorKind::ParseXml,
+                ))
+            }
+        }
+        rest = &rest[name.len() + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+const XINCLUDE_NAMESPACE: &str = "http://www.w3.org/2001/XInclude";
+
+/// Replaces all XInclude `include` elements in `xml` by the document
+/// fragments returned by `resolver` for their `href` attribute.
+///
+/// Included fragments are expanded recursively, circular includes result
+/// in an error.
+pub(crate) fn expand_includes<R>(xml: &str, resolver: &R) -> Result<String, Error>
+where
+    R: Fn(&str) -> Result<String, Error>,
+{
+    expand_includes_rec(xml, resolver, &[], &mut Vec::new())
+}
+
+fn expand_includes_rec<R>(
+    xml: &str,
+    resolver: &R,
+    parent_prefixes: &[String],
+    hrefs: &mut Vec<String>,
+) -> Result<String, Error>
+where
+    R: Fn(&str) -> Result<String, Error>,
+{
+    let mut prefixes = parent_prefixes.to_vec();
+    prefixes.extend(xinclude_prefixes(xml));
+
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some((start, prefix)) = find_include(rest, &prefixes) {
+        out.push_str(&rest[..start]);
+        let tag_end =
+            tag_end(rest, start).ok_or_else(|| include_error("Unterminated XInclude element."))?;
+        let tag = &rest[start..tag_end];
+        let end = if tag.ends_with("/>") {
+            tag_end
+        } else {
+            let closing = format!("</{}:include>", prefix);
+            find_from(rest, &closing, tag_end)
+                .map(|i| i + closing.len())
+                .ok_or_else(|| include_error("Unterminated XInclude element."))?
+        };
+
+        let href = attribute_value(tag, "href")
+            .ok_or_else(|| include_error("XInclude element without href."))?;
+        if hrefs.iter().any(|h| h == href) {
+            return Err(include_error(format!("Circular XInclude of '{}'.", href)));
+        }
+        let fragment = resolver(href)?;
+        hrefs.push(href.to_string());
+        let expanded =
+            expand_includes_rec(strip_xml_declaration(&fragment), resolver, &prefixes, hrefs)?;
+        hrefs.pop();
+
+        out.push_str(&expanded);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Returns the prefixes bound to the XInclude namespace in `xml`.
+fn xinclude_prefixes(xml: &str) -> Vec<String> {
+    let mut prefixes = Vec::new();
+    let mut rest = xml;
+    while let Some(pos) = rest.find("xmlns:") {
+        rest = &rest[pos + "xmlns:".len()..];
+        let prefix = rest.split('=').next().unwrap_or("").trim();
+        let value = rest[prefix.len()..]
+            .trim_start()
+            .trim_start_matches('=')
+            .trim_start();
+        let uri = value.get(1..).and_then(|v| v.split(['"', '\'']).next());
+        if uri == Some(XINCLUDE_NAMESPACE) {
+            prefixes.push(prefix.to_string());
+        }
+    }
+    prefixes
+}
+
+/// Finds the first XInclude element using one of `prefixes`, skipping
+/// comments, CDATA sections and processing instructions.
+fn find_include<'p>(xml: &str, prefixes: &'p [String]) -> Option<(usize, &'p str)> {
+    let mut pos = 0;
+    while let Some(start) = find_from(xml, "<", pos) {
+        let rest = &xml[start..];
+        pos = if rest.starts_with("<!--") {
+            find_from(xml, "-->", start)? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            find_from(xml, "]]>", start)? + 3
+        } else if rest.starts_with("<?") {
+            find_from(xml, "?>", start)? + 2
+        } else {
+            let prefix = prefixes.iter().find(|prefix| {
+                rest[1..]
+                    .strip_prefix(prefix.as_str())
+                    .and_then(|after| after.strip_prefix(":include"))
+                    .map_or(false, |after| {
+                        after.starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                    })
+            });
+            if let Some(prefix) = prefix {
+                return Some((start, prefix.as_str()));
+            }
+            start + 1
+        };
+    }
+    None
+}
+
+/// Returns the value of the attribute `name` in the start tag `tag`.
+fn attribute_value<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{}=", name);
+    let mut pos = 0;
+    while let Some(start) = find_from(tag, &pattern, pos) {
+        let preceded_by_space = tag[..start].ends_with(char::is_whitespace);
+        let value = &tag[start + pattern.len()..];
+        if preceded_by_space {
+            let quote = value.chars().next()?;
+            if quote == '"' || quote == '\'' {
+                return value[1..].split(quote).next();
+            }
+        }
+        pos = start + pattern.len();
+    }
+    None
+}
+
+fn strip_xml_declaration(xml: &str) -> &str {
+    let trimmed = xml.trim_start();
+    if trimmed.starts_with("<?xml") {
+        if let Some(end) = trimmed.find("?>") {
+            return &trimmed[end + 2..];
+        }
+    }
+    xml
+}
+
+fn include_error<S: Into<String>>(msg: S) -> Error {
+    Error::internal(msg.into(), ErrorKind::ParseXml)
+}
+
+/// Returns the position after the end of the tag starting at `start`.
+pub(crate) fn tag_end(xml: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in xml[start..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(start + i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+pub(crate) fn find_from(xml: &str, pattern: &str, pos: usize) -> Option<usize> {
+    xml[pos..].find(pattern).map(|i| pos + i)
+}
+
+/// Returns the position of the `n`-th (counting from zero) start tag of an
+/// element named `name`, skipping comments, CDATA sections and processing
+/// instructions.
+pub(crate) fn nth_start_tag(xml: &str, name: &str, n: usize) -> Option<usize> {
+    let mut pos = 0;
+    let mut count = 0;
+    while let Some(start) = find_from(xml, "<", pos) {
+        let rest = &xml[start..];
+        pos = if rest.starts_with("<!--") {
+            find_from(xml, "-->", start)? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            find_from(xml, "]]>", start)? + 3
+        } else if rest.starts_with("<?") {
+            find_from(xml, "?>", start)? + 2
+        } else {
+            let matches = match rest[1..].strip_prefix(name) {
+                Some(after) => {
+                    after.starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+                }
+                None => false,
+            };
+            if matches {
+                if count == n {
+                    return Some(start);
+                }
+                count += 1;
+            }
+            start + 1
+        };
+    }
+    None
+}